@@ -5,7 +5,12 @@ use std::path::PathBuf;
 fn main() -> Result<()> {
     let proto_roots = ["protos", "protos/prometheus/prompb"];
 
-    let protos_to_compile = vec!["prometheus/prompb/remote.proto"];
+    let protos_to_compile = vec![
+        "prometheus/prompb/remote.proto",
+        "prometheus/prompb/io/prometheus/write/v2/types.proto",
+        "opentelemetry/proto/collector/trace/v1/trace_service.proto",
+        "opentelemetry/proto/collector/metrics/v1/metrics_service.proto",
+    ];
 
     let mut config = prost_build::Config::new();
 
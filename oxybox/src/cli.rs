@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+
+use crate::config::app_config::{
+    load_config, load_probe_config, setup_resolver, setup_tls_connector,
+};
+use crate::config::interpolate::SECRETS;
+use crate::config::probe_config::{OrganisationConfig, TargetConfig};
+use crate::config::settings::Settings;
+use crate::exposition::format::{ExpositionFormat, render};
+use crate::http_probe::probe::{ProbeContext, probe_target};
+use crate::mimir::client::MimirEndpoint;
+use crate::mimir::metadata::{prefixed_probe_metadata, probe_metadata};
+use crate::mimir::{HTTP_MODULE, create_probe_metrics, label_series};
+use crate::server::probe::debug_log;
+
+/// The command-line interface of Oxybox. Without a command, Oxybox runs.
+#[derive(Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub settings: Settings,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Probes the targets of every organisation and writes their metrics to the sinks (default).
+    Run(Settings),
+
+    /// Checks a probe configuration file, exiting with 1 when it is invalid.
+    Validate {
+        /// The probe configuration file.
+        #[arg(env = "CONFIG_FILE", default_value = "config.yml")]
+        file: String,
+    },
+
+    /// Probes a single URL once, printing every phase of the probe and the resulting metrics.
+    /// Exits with 1 when the probe fails.
+    Check {
+        /// The URL to probe.
+        url: String,
+
+        /// The tenant in the probe configuration file the URL is a target of. The target is probed
+        /// with its headers and TLS settings, and its metrics are labeled like when running.
+        #[arg(long)]
+        tenant: Option<String>,
+
+        /// The status codes the probe accepts, comma separated. Defaults to those of the target, or 200.
+        #[arg(long, value_delimiter = ',')]
+        accepted_status_codes: Vec<u16>,
+
+        /// How long the probe may take. Defaults to the timeout of the target, or 10 seconds.
+        #[arg(long)]
+        timeout_seconds: Option<u64>,
+
+        #[command(flatten)]
+        settings: Settings,
+    },
+
+    /// Prints the settings and the probe configuration as Oxybox applies them.
+    PrintConfig(Settings),
+}
+
+/// Validates a probe configuration file, for `oxybox validate <file>`.
+/// Prints the problems found and exits with a failure if the configuration is invalid.
+pub fn validate(file: &str) -> ExitCode {
+    match load_probe_config(file) {
+        Ok(config) => {
+            let targets: usize = config.values().map(|org| org.targets.len()).sum();
+            println!(
+                "{file} is valid: {} organisations, {targets} targets",
+                config.len()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", SECRETS.redact(&e.to_string()));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The default timeout of `oxybox check`, when neither the command line nor the target sets one.
+const DEFAULT_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Probes a URL once, for `oxybox check <url>`, using the same probe as the probe loops.
+/// Prints a log of every phase of the probe, followed by its metrics in the Prometheus text format.
+/// With a tenant, the URL is probed as the target of the tenant in the probe configuration file,
+/// including the defaults it inherits, and its metrics are labeled and named as they are pushed.
+/// # Arguments
+///     * `url` - The URL to probe.
+///     * `tenant` - The tenant the URL is a target of, if any.
+///     * `accepted_status_codes` - The status codes for which the probe succeeds, those of the
+///       target when empty.
+///     * `timeout` - How long the probe may take, that of the target when not set.
+///     * `settings` - The settings, of which the DNS hosts, sample timestamp and probe configuration
+///       file are used.
+/// # Returns
+///     Success when the probe succeeded, failure otherwise.
+pub async fn check(
+    url: &str,
+    tenant: Option<&str>,
+    accepted_status_codes: &[u16],
+    timeout: Option<Duration>,
+    settings: Settings,
+) -> ExitCode {
+    let (mut target, org_config) = match tenant {
+        Some(tenant) => match find_target(&settings.config_file, tenant, url) {
+            Ok((target, org_config)) => (target, Some(org_config)),
+            Err(e) => {
+                eprintln!("{}", SECRETS.redact(&e));
+                return ExitCode::FAILURE;
+            }
+        },
+        None => (
+            TargetConfig {
+                url: url.to_string(),
+                ..Default::default()
+            },
+            None,
+        ),
+    };
+    let timeout = timeout
+        .or(target.timeout_seconds.map(Duration::from_secs))
+        .unwrap_or(DEFAULT_CHECK_TIMEOUT);
+    target.timeout_seconds = Some(timeout.as_secs());
+    if !accepted_status_codes.is_empty() {
+        target.accepted_status_codes = Some(accepted_status_codes.to_vec());
+    }
+
+    let resolver = match setup_resolver(&settings.dns_hosts) {
+        Ok(resolver) => resolver,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let context = ProbeContext {
+        resolver,
+        tls_connector: setup_tls_connector().expect("Failed to build TLS connector"),
+        mimir_endpoint: MimirEndpoint {
+            url: settings.mimir_endpoint,
+            remote_write_version: settings.mimir_remote_write_version,
+            metadata_send_interval: Duration::from_secs(
+                settings.mimir_metadata_send_interval_seconds,
+            ),
+        },
+        external_labels: Default::default(),
+        max_org_width: 0,
+        staleness_markers: false,
+        sample_timestamp: settings.sample_timestamp,
+        otlp_traces_endpoint: None,
+    };
+
+    let (probe, error) = probe_target(&target, &context).await;
+    let success = error.is_none()
+        && probe
+            .http_status
+            .is_some_and(|code| target.accepted_status_codes().contains(&code));
+
+    let timestamp_ms = probe.sample_timestamp_ms(context.sample_timestamp);
+    let mut metrics = create_probe_metrics(&probe, success, timestamp_ms);
+    let metadata = match &org_config {
+        Some(org_config) => {
+            label_series(&mut metrics, &target, org_config);
+            prefixed_probe_metadata(&org_config.metric_prefix)
+        }
+        None => probe_metadata(),
+    };
+    print!(
+        "{}",
+        SECRETS.redact(&debug_log(
+            &probe,
+            HTTP_MODULE,
+            timeout,
+            success,
+            error.as_deref()
+        ))
+    );
+    println!("\nMetrics:");
+    print!(
+        "{}",
+        render(&metrics, ExpositionFormat::Prometheus, &metadata)
+    );
+
+    match success {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
+
+/// Looks up a target of a tenant in the probe configuration, for `oxybox check --tenant`.
+/// # Arguments
+///     * `config_file` - The probe configuration file, directory or glob pattern.
+///     * `tenant` - The name of the tenant.
+///     * `url` - The URL of the target.
+/// # Returns
+///     The target, with the defaults of the organisation applied, and the configuration of the
+///     organisation.
+/// # Errors
+///     Returns an error if the configuration is invalid, or has no such tenant or target.
+fn find_target(
+    config_file: &str,
+    tenant: &str,
+    url: &str,
+) -> Result<(TargetConfig, OrganisationConfig), String> {
+    let mut config = load_probe_config(config_file).map_err(|e| e.to_string())?;
+    let org_config = config
+        .remove(tenant)
+        .ok_or_else(|| format!("{config_file} has no tenant {tenant}"))?;
+    let target = org_config
+        .targets
+        .iter()
+        .find(|target| target.url == url)
+        .ok_or_else(|| format!("Tenant {tenant} has no target {url}"))?
+        .clone();
+    Ok((target, org_config))
+}
+
+/// The settings and probe configuration as Oxybox applies them, printed by `oxybox print-config`.
+#[derive(Serialize)]
+struct EffectiveConfig<'a> {
+    settings: &'a Settings,
+    organisations: BTreeMap<&'a String, &'a OrganisationConfig>,
+}
+
+/// Prints the settings and the probe configuration as YAML, for `oxybox print-config`.
+/// The configuration is loaded and validated exactly as when running, and its secrets are redacted.
+pub fn print_config(settings: Settings) -> ExitCode {
+    let app_config = match load_config(settings.clone()) {
+        Ok(app_config) => app_config,
+        Err(e) => {
+            eprintln!("{}", SECRETS.redact(&e.to_string()));
+            return ExitCode::FAILURE;
+        }
+    };
+    let effective = EffectiveConfig {
+        settings: &settings,
+        organisations: app_config.config.iter().collect(),
+    };
+    match serde_yaml::to_string(&effective) {
+        Ok(yaml) => {
+            print!("{}", SECRETS.redact(&yaml));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Failed to print the configuration: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["oxybox", "--listen-address", "127.0.0.1:9116"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.settings.listen_address.unwrap().port(), 9116);
+        let cli = Cli::try_parse_from(["oxybox", "--listen-address", "off"]).unwrap();
+        assert!(cli.settings.listen_address.is_none());
+
+        let cli = Cli::try_parse_from([
+            "oxybox",
+            "check",
+            "https://example.com",
+            "--tenant",
+            "demo",
+            "--accepted-status-codes",
+            "200,301",
+            "--dns-hosts",
+            "9.9.9.9",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Command::Check {
+                url,
+                tenant,
+                accepted_status_codes,
+                timeout_seconds,
+                settings,
+            }) => {
+                assert_eq!(url, "https://example.com");
+                assert_eq!(tenant.as_deref(), Some("demo"));
+                assert_eq!(accepted_status_codes, vec![200, 301]);
+                assert_eq!(timeout_seconds, None);
+                assert_eq!(settings.dns_hosts, vec!["9.9.9.9"]);
+            }
+            _ => panic!("Expected the check command"),
+        }
+
+        assert!(Cli::try_parse_from(["oxybox", "--ha-cluster", "eu"]).is_err());
+    }
+}
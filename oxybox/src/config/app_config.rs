@@ -1,5 +1,9 @@
+use std::collections::HashMap;
 use std::env;
-use std::{net::IpAddr, time::Duration};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
 
 use tokio_native_tls::TlsConnector as TokioTlsConnector;
 use trust_dns_resolver::{
@@ -7,44 +11,279 @@ use trust_dns_resolver::{
     config::{NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts},
 };
 
-use super::probe_config::Config;
+use crate::http_probe::result::SampleTimestamp;
+use crate::mimir::client::{ExternalLabels, MimirEndpoint};
+use crate::otlp::client::OtlpEndpoint;
+
+use super::error::{ConfigError, ConfigProblem};
+use super::interpolate::{SECRETS, Secrets, interpolate};
+use super::probe_config::{Config, ConfigFile, DEFAULTS_KEY, OrganisationConfig, TargetDefaults};
+use super::settings::Settings;
+use super::sources::config_files;
+use super::validate::{validate, validate_dns_hosts};
 
 pub struct AppConfig {
     pub config: Config,
-    pub mimir_endpoint: String,
+    pub config_file: String,
+    pub config_reload_interval: Duration,
+    pub mimir_endpoint: MimirEndpoint,
+    pub external_labels: ExternalLabels,
     pub dns_hosts: Vec<String>,
     pub max_org_width: usize,
+    pub staleness_markers: bool,
+    pub sample_timestamp: SampleTimestamp,
+    pub otlp_traces_endpoint: Option<OtlpEndpoint>,
+    pub listen_address: Option<SocketAddr>,
+    pub admin_tenant: Option<String>,
+    pub admin_push_interval: Duration,
+    pub readiness_max_write_age: Duration,
+    pub shutdown_timeout: Duration,
 }
 
-/// Load the application configuration from a YAML file and environment variables
-/// This function reads the configuration file specified by the `CONFIG_FILE` environment variable,
-/// parses it into a `Config` struct, and overrides certain values with environment variables.
-/// It also sets up the DNS hosts and Mimir endpoint.
-pub fn load_config() -> AppConfig {
-    let config_file_location = env::var("CONFIG_FILE").unwrap_or_else(|_| "config.yml".to_string());
-    let config_str =
-        std::fs::read_to_string(&config_file_location).expect("Failed to read config.yaml");
-
-    let config: Config = serde_yaml::from_str(&config_str).expect("Invalid YAML");
+/// Load the application configuration from the settings and the probe configuration file.
+/// This function reads the probe configuration file named by `--config-file` (`CONFIG_FILE`),
+/// and sets up the DNS hosts, Mimir endpoint, remote write protocol version and external labels.
+/// When `HA_CLUSTER` and `HA_REPLICA` are set, they are added to the external labels as the
+/// `cluster` and `__replica__` labels used by Mimir's HA tracker to deduplicate replicated pushes,
+/// replacing labels of the series with the same name.
+/// When `OTLP_TRACES_ENDPOINT` is set, a trace of every probe is exported to that collector.
+/// The HTTP server exposing the latest probe results listens on `LISTEN_ADDRESS`, unless it is `off`.
+/// The probe configuration file is checked for changes every `CONFIG_RELOAD_INTERVAL_SECONDS`.
+/// When `ADMIN_TENANT` is set, the metrics Oxybox keeps about itself are pushed to that tenant.
+/// # Arguments
+///     * `settings` - The settings, from the command-line flags and environment variables.
+/// # Errors
+///     Returns an error pointing at the offending file and field if the probe configuration cannot be
+///     read, parsed or validated, if `DNS_HOSTS` contains anything but IP addresses, or if
+///     `EXTERNAL_LABELS` or `OTLP_TRACES_HEADERS` contain a malformed `name=value` pair.
+pub fn load_config(settings: Settings) -> Result<AppConfig, ConfigError> {
+    let config = load_probe_config(&settings.config_file)?;
 
-    let dns_hosts = env::var("DNS_HOSTS")
-        .unwrap_or_else(|_| "1.1.1.1,8.8.8.8".to_string())
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .collect();
+    let dns_hosts = settings.dns_hosts;
+    let mut problems = validate_dns_hosts(&dns_hosts);
+    let labels = parse_env_labels("EXTERNAL_LABELS", settings.external_labels, &mut problems);
+    let otlp_traces_headers = parse_env_labels(
+        "OTLP_TRACES_HEADERS",
+        settings.otlp_traces_headers,
+        &mut problems,
+    );
+    if !problems.is_empty() {
+        return Err(ConfigError::Environment(problems));
+    }
 
     log::info!("Using DNS hosts: {:?}", dns_hosts);
 
-    let mimir_endpoint =
-        env::var("MIMIR_ENDPOINT").unwrap_or_else(|_| "http://localhost:9009".to_string());
+    let mut external_labels = ExternalLabels {
+        labels,
+        ha_labels: vec![],
+    };
+
+    if let (Some(cluster), Some(replica)) = (settings.ha_cluster, settings.ha_replica) {
+        log::info!("Running as replica {replica} of HA cluster {cluster}");
+        external_labels.ha_labels = vec![
+            (settings.ha_cluster_label, cluster),
+            (settings.ha_replica_label, replica),
+        ];
+    }
+
+    if !external_labels.is_empty() {
+        log::info!("Using external labels: {:?}", external_labels);
+    }
+
+    let mimir_endpoint = MimirEndpoint {
+        url: settings.mimir_endpoint,
+        remote_write_version: settings.mimir_remote_write_version,
+        metadata_send_interval: Duration::from_secs(settings.mimir_metadata_send_interval_seconds),
+    };
+
+    let otlp_traces_endpoint = settings.otlp_traces_endpoint.map(|url| OtlpEndpoint {
+        url,
+        protocol: settings.otlp_traces_protocol,
+        headers: otlp_traces_headers.into_iter().collect(),
+    });
+
+    if let Some(endpoint) = &otlp_traces_endpoint {
+        log::info!(
+            "Exporting probe traces to: {} ({:?})",
+            endpoint.url,
+            endpoint.protocol
+        );
+    }
 
     let max_org_width = config.keys().map(|org| org.len()).max().unwrap_or(10);
 
-    AppConfig {
+    Ok(AppConfig {
         config,
+        config_file: settings.config_file,
+        config_reload_interval: Duration::from_secs(settings.config_reload_interval_seconds),
         mimir_endpoint,
+        external_labels,
         dns_hosts,
         max_org_width,
+        staleness_markers: settings.staleness_markers,
+        sample_timestamp: settings.sample_timestamp,
+        otlp_traces_endpoint,
+        listen_address: settings.listen_address,
+        admin_tenant: settings.admin_tenant,
+        admin_push_interval: Duration::from_secs(settings.admin_push_interval_seconds),
+        readiness_max_write_age: Duration::from_secs(settings.readiness_max_write_age_seconds),
+        shutdown_timeout: Duration::from_secs(settings.shutdown_timeout_seconds),
+    })
+}
+
+/// Reads, parses and validates the probe configuration, from a file, or from every file in a
+/// directory or matching a glob pattern. Every file contributes one or more organisations, and an
+/// organisation may only be defined in one file. So may the top-level `defaults`, which apply to the
+/// organisations of every file.
+/// The `${...}` references in the files are resolved first, and the secrets among the values
+/// substituted for them are registered, so they are redacted from the logs.
+/// # Arguments
+///     * `path` - The path of the probe configuration file or directory, or a glob pattern.
+/// # Returns
+///     The probe configuration of all files merged.
+/// # Errors
+///     Returns an error if a file cannot be read, has a reference which cannot be resolved, does not
+///     match the configuration structure, or contains invalid values. Parse errors carry the line,
+///     column and path of the offending field. When the configuration is split across files, the
+///     problems name the file they are in.
+///     The secrets substituted into the files are only added to `SECRETS` once the configuration
+///     is valid, and are redacted from the error otherwise.
+pub fn load_probe_config(path: &str) -> Result<Config, ConfigError> {
+    let mut secrets = Vec::new();
+    match merge_probe_config(path, &mut secrets) {
+        Ok(config) => {
+            SECRETS.add(secrets);
+            Ok(config)
+        }
+        Err(error) => {
+            let local = Secrets::default();
+            local.add(secrets);
+            Err(error.redact(&local))
+        }
+    }
+}
+
+/// Reads, merges and validates the probe configuration files, see `load_probe_config`.
+/// # Arguments
+///     * `path` - The path of the probe configuration file or directory, or a glob pattern.
+///     * `secrets` - Collects the secrets substituted into the files.
+fn merge_probe_config(path: &str, secrets: &mut Vec<String>) -> Result<Config, ConfigError> {
+    let mut config = Config::new();
+    let mut sources: HashMap<String, String> = HashMap::new();
+    let mut defaults: Option<(TargetDefaults, String)> = None;
+    let mut problems = Vec::new();
+
+    for file in config_files(path)? {
+        let config_file = parse_probe_config(&file, secrets)?;
+        if let Some(file_defaults) = config_file.defaults {
+            match &defaults {
+                Some((_, other)) => problems.push(
+                    ConfigProblem::new(DEFAULTS_KEY, format!("is also defined in {other}"))
+                        .in_file(&file),
+                ),
+                None => defaults = Some((file_defaults, file.clone())),
+            }
+        }
+        let mut names: Vec<(String, OrganisationConfig)> =
+            config_file.organisations.into_iter().collect();
+        names.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (name, org_config) in names {
+            if let Some(other) = sources.get(&name) {
+                problems.push(
+                    ConfigProblem::new(name.as_str(), format!("is also defined in {other}"))
+                        .in_file(&file),
+                );
+                continue;
+            }
+            sources.insert(name.clone(), file.clone());
+            config.insert(name, org_config);
+        }
+    }
+    let config = ConfigFile {
+        defaults: defaults.map(|(defaults, _)| defaults),
+        organisations: config,
+    }
+    .into_config();
+
+    problems.extend(validate(&config).into_iter().map(|(name, problem)| {
+        match sources[name] == path {
+            true => problem,
+            false => problem.in_file(&sources[name]),
+        }
+    }));
+    match problems.is_empty() {
+        true => Ok(config),
+        false => Err(ConfigError::Invalid {
+            file: path.to_string(),
+            problems,
+        }),
+    }
+}
+
+/// Reads and parses a single probe configuration file, resolving its `${...}` references. The
+/// secrets substituted into it are added to `secrets`.
+fn parse_probe_config(file: &str, secrets: &mut Vec<String>) -> Result<ConfigFile, ConfigError> {
+    let config_str = std::fs::read_to_string(file).map_err(|source| ConfigError::Read {
+        file: file.to_string(),
+        source,
+    })?;
+    let interpolated = interpolate(file, &config_str, |name| env::var(name).ok())?;
+    secrets.extend(interpolated.secrets);
+    let config_file: ConfigFile =
+        serde_path_to_error::deserialize(serde_yaml::Deserializer::from_str(&interpolated.text))
+            .map_err(
+                |e| match (ConfigError::parse(file, e), interpolated.rewritten) {
+                    // the location points into the rewritten text, the path still points at the field
+                    (ConfigError::Parse { path, message, .. }, true) => ConfigError::Parse {
+                        file: file.to_string(),
+                        path,
+                        location: None,
+                        message,
+                    },
+                    (error, _) => error,
+                },
+            )?;
+    Ok(config_file)
+}
+
+/// Parses a comma separated list of `name=value` pairs into labels, or headers.
+/// # Arguments
+///     * `labels` - The labels to parse (e.g., "region=eu-west, probe_location=ams").
+/// # Returns
+///     A vector of label name and value tuples, or an error if a pair is malformed.
+fn parse_labels(labels: &str) -> Result<Vec<(String, String)>, String> {
+    labels
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                Ok((name.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(format!("Expected a label as name=value, got '{pair}'")),
+        })
+        .collect()
+}
+
+/// Parses the `name=value` pairs of an environment variable, adding a problem when one is malformed.
+/// # Arguments
+///     * `variable` - The name of the environment variable, to report problems with.
+///     * `value` - The value of the environment variable, if it is set.
+///     * `problems` - The problems found so far, to which a malformed pair is added.
+/// # Returns
+///     The parsed pairs, or none if the variable is unset or malformed.
+fn parse_env_labels(
+    variable: &str,
+    value: Option<String>,
+    problems: &mut Vec<ConfigProblem>,
+) -> Vec<(String, String)> {
+    match value.as_deref().map(parse_labels) {
+        Some(Ok(labels)) => labels,
+        Some(Err(message)) => {
+            problems.push(ConfigProblem::new(variable, message));
+            vec![]
+        }
+        None => vec![],
     }
 }
 
@@ -63,9 +302,14 @@ pub fn setup_tls_connector() -> Result<TokioTlsConnector, native_tls::Error> {
 ///     * `dns_hosts` - A slice of strings representing DNS host IPs (e.g., "
 /// # Returns
 ///     A `Result` containing a `TokioAsyncResolver` if successful, or an error if the setup fails.
-pub fn setup_resolver(
-    dns_hosts: &[String],
-) -> Result<TokioAsyncResolver, Box<dyn std::error::Error>> {
+/// # Errors
+///     Returns `ConfigError::Environment` listing the DNS hosts that are not IP addresses.
+pub fn setup_resolver(dns_hosts: &[String]) -> Result<TokioAsyncResolver, ConfigError> {
+    let problems = validate_dns_hosts(dns_hosts);
+    if !problems.is_empty() {
+        return Err(ConfigError::Environment(problems));
+    }
+
     let mut opts = ResolverOpts::default();
     opts.attempts = 2;
     opts.timeout = Duration::from_millis(100);
@@ -73,8 +317,10 @@ pub fn setup_resolver(
 
     let mut name_servers = NameServerConfigGroup::new();
 
-    for host in dns_hosts {
-        let ip: IpAddr = host.parse()?;
+    for ip in dns_hosts
+        .iter()
+        .filter_map(|host| host.parse::<IpAddr>().ok())
+    {
         name_servers.push(NameServerConfig {
             socket_addr: (ip, 53).into(),
             protocol: Protocol::Tcp,
@@ -87,3 +333,108 @@ pub fn setup_resolver(
     let resolver_config = ResolverConfig::from_parts(None, vec![], name_servers);
     Ok(TokioAsyncResolver::tokio(resolver_config, opts))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_labels() {
+        let labels = parse_labels("region=eu-west, probe_location = ams,").unwrap();
+        assert_eq!(
+            labels,
+            vec![
+                ("region".to_string(), "eu-west".to_string()),
+                ("probe_location".to_string(), "ams".to_string()),
+            ]
+        );
+        assert!(parse_labels("").unwrap().is_empty());
+        assert!(parse_labels("region").is_err());
+        assert!(parse_labels("=eu-west").is_err());
+    }
+
+    #[test]
+    fn test_parse_env_labels() {
+        let mut problems = vec![];
+        let labels = parse_env_labels(
+            "EXTERNAL_LABELS",
+            Some("region=eu-west".to_string()),
+            &mut problems,
+        );
+        assert_eq!(labels, vec![("region".to_string(), "eu-west".to_string())]);
+        assert!(parse_env_labels("EXTERNAL_LABELS", None, &mut problems).is_empty());
+        assert!(problems.is_empty());
+
+        let labels = parse_env_labels(
+            "OTLP_TRACES_HEADERS",
+            Some("x-api-key".to_string()),
+            &mut problems,
+        );
+        assert!(labels.is_empty());
+        assert_eq!(
+            problems.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec!["OTLP_TRACES_HEADERS: Expected a label as name=value, got 'x-api-key'"]
+        );
+        assert!(setup_resolver(&["resolver.example.com".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_load_probe_config() {
+        let path = std::env::temp_dir().join(format!("oxybox-{}.yml", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "demo:\n  organisation_id: demo\n  polling_interval_seconds: ten\n  targets: []\n",
+        )
+        .unwrap();
+
+        let error = load_probe_config(path).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{path}:3:29: demo.polling_interval_seconds: invalid type: string \"ten\", expected u64"
+            )
+        );
+        assert!(matches!(
+            load_probe_config(path),
+            Err(ConfigError::Read { .. })
+        ));
+    }
+
+    #[test]
+    fn test_load_probe_config_directory() {
+        let dir = std::env::temp_dir().join(format!("oxybox-conf.d-load-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tenant = |name: &str| {
+            format!(
+                "{name}:\n  organisation_id: {name}\n  polling_interval_seconds: 10\n  targets:\n    - url: https://{name}.example.com\n"
+            )
+        };
+        std::fs::write(dir.join("acme.yml"), tenant("acme")).unwrap();
+        std::fs::write(
+            dir.join("defaults.yml"),
+            "defaults:\n  timeout_seconds: 7\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("globex.yml"), tenant("globex")).unwrap();
+        let dir_str = dir.to_str().unwrap().to_string();
+
+        let config = load_probe_config(&dir_str).unwrap();
+        assert_eq!(config.len(), 2);
+        // the defaults of one file apply to the organisations of every file
+        assert_eq!(config["acme"].targets[0].timeout_seconds, Some(7));
+        assert_eq!(config["globex"].targets[0].timeout_seconds, Some(7));
+
+        let other = format!("defaults:\n  timeout_seconds: 3\n{}", tenant("acme"));
+        std::fs::write(dir.join("other.yml"), other).unwrap();
+        let error = load_probe_config(&dir_str).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{dir_str} is invalid:\n  - {dir_str}/other.yml: defaults: is also defined in {dir_str}/defaults.yml\n  - {dir_str}/other.yml: acme: is also defined in {dir_str}/acme.yml"
+            )
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+
+use super::interpolate::Secrets;
+
+/// A problem found in a configuration which parsed, such as a target with an invalid URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    /// The path of the offending field, e.g. `demo.targets[1].url`.
+    pub path: String,
+
+    /// What is wrong with the field.
+    pub message: String,
+}
+
+impl ConfigProblem {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        ConfigProblem {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Prefixes the path with the file the problem is in, for configurations split across files.
+    pub fn in_file(self, file: &str) -> Self {
+        ConfigProblem {
+            path: format!("{file}: {}", self.path),
+            message: self.message,
+        }
+    }
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// An error loading the probe configuration, pointing at the file and field that caused it.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    Read {
+        file: String,
+        source: std::io::Error,
+    },
+
+    /// A `${...}` reference in the configuration file cannot be resolved.
+    Interpolation {
+        file: String,
+        /// The line and column of the reference, both starting at 1.
+        location: Option<(usize, usize)>,
+        message: String,
+    },
+
+    /// The configuration file is not valid YAML, or does not match the configuration structure.
+    Parse {
+        file: String,
+        /// The path of the field which failed to parse, e.g. `demo.polling_interval_seconds`.
+        path: String,
+        /// The line and column of the error, both starting at 1.
+        location: Option<(usize, usize)>,
+        message: String,
+    },
+
+    /// The configuration parsed, but contains invalid values.
+    Invalid {
+        file: String,
+        problems: Vec<ConfigProblem>,
+    },
+
+    /// Environment variables contain invalid values.
+    Environment(Vec<ConfigProblem>),
+}
+
+impl ConfigError {
+    /// Creates a parse error from the error of deserializing a YAML document.
+    /// The path and location serde_yaml puts into its message are stripped, as they are kept apart.
+    /// For errors raised while deserializing a field, serde_yaml puts the path of the enclosing
+    /// value into the message, which is stripped as well.
+    pub fn parse(file: &str, error: serde_path_to_error::Error<serde_yaml::Error>) -> Self {
+        let path = error.path().to_string();
+        let inner = error.into_inner();
+        let location = inner
+            .location()
+            .map(|location| (location.line(), location.column()));
+
+        let message = inner.to_string();
+        let mut message = message.as_str();
+        if let Some((prefix, rest)) = message.split_once(": ") {
+            if !prefix.is_empty() && path.starts_with(prefix) {
+                message = rest;
+            }
+        }
+        if let Some((line, column)) = location {
+            message = message
+                .strip_suffix(&format!(" at line {line} column {column}"))
+                .unwrap_or(message);
+        }
+
+        ConfigError::Parse {
+            file: file.to_string(),
+            path,
+            location,
+            message: message.to_string(),
+        }
+    }
+
+    /// Redacts the secrets from the messages of the error, as they may quote the offending values.
+    /// # Arguments
+    ///     * `secrets` - The secrets to redact.
+    /// # Returns
+    ///     The error with its messages redacted.
+    pub fn redact(self, secrets: &Secrets) -> Self {
+        let redact_problems = |problems: Vec<ConfigProblem>| {
+            problems
+                .into_iter()
+                .map(|problem| ConfigProblem {
+                    message: secrets.redact(&problem.message).into_owned(),
+                    ..problem
+                })
+                .collect()
+        };
+        match self {
+            ConfigError::Interpolation {
+                file,
+                location,
+                message,
+            } => ConfigError::Interpolation {
+                file,
+                location,
+                message: secrets.redact(&message).into_owned(),
+            },
+            ConfigError::Parse {
+                file,
+                path,
+                location,
+                message,
+            } => ConfigError::Parse {
+                file,
+                path,
+                location,
+                message: secrets.redact(&message).into_owned(),
+            },
+            ConfigError::Invalid { file, problems } => ConfigError::Invalid {
+                file,
+                problems: redact_problems(problems),
+            },
+            ConfigError::Environment(problems) => {
+                ConfigError::Environment(redact_problems(problems))
+            }
+            error @ ConfigError::Read { .. } => error,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { file, source } => write!(f, "Failed to read {file}: {source}"),
+            ConfigError::Interpolation {
+                file,
+                location,
+                message,
+            } => {
+                write!(f, "{file}")?;
+                if let Some((line, column)) = location {
+                    write!(f, ":{line}:{column}")?;
+                }
+                write!(f, ": {message}")
+            }
+            ConfigError::Parse {
+                file,
+                path,
+                location,
+                message,
+            } => {
+                write!(f, "{file}")?;
+                if let Some((line, column)) = location {
+                    write!(f, ":{line}:{column}")?;
+                }
+                match path.as_str() {
+                    "" | "." => write!(f, ": {message}"),
+                    path => write!(f, ": {path}: {message}"),
+                }
+            }
+            ConfigError::Invalid { file, problems } => {
+                write!(f, "{file} is invalid:")?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            }
+            ConfigError::Environment(problems) => {
+                write!(f, "Invalid environment variables:")?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
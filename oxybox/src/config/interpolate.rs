@@ -0,0 +1,404 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, RwLock};
+
+use serde::{Serialize, Serializer};
+use serde_yaml::{Mapping, Value};
+
+use super::error::ConfigError;
+
+/// What a secret is replaced with in logs and configuration dumps.
+pub const REDACTED: &str = "<redacted>";
+
+/// The fields holding credentials. The values substituted anywhere below them are secrets.
+const SECRET_FIELDS: [&str; 2] = ["headers", "token"];
+
+/// A probe configuration with its `${...}` references resolved.
+pub struct Interpolated {
+    /// The configuration text.
+    pub text: String,
+
+    /// Whether the text was rewritten, in which case parse errors no longer point at the lines of
+    /// the configuration file.
+    pub rewritten: bool,
+
+    /// The values substituted from files, and from environment variables into `headers` and
+    /// `token` fields, to be redacted.
+    pub secrets: Vec<String>,
+}
+
+/// Resolves the references in the string values and keys of a probe configuration file:
+///     * `${VAR}` - The value of environment variable `VAR`, which must be set.
+///     * `${VAR:-default}` - The value of `VAR`, or `default` when it is unset or empty.
+///     * `${file:/path}` - The contents of a file, without trailing line breaks, e.g. a mounted secret.
+///     * `$${` - A literal `${`.
+///
+/// The file is parsed as YAML first, so references in comments are left alone and a substituted
+/// value can never change the structure of the configuration. A value consisting of a single
+/// reference is typed as YAML would, e.g. `polling_interval_seconds: ${INTERVAL}` becomes a number.
+/// The values substituted from files are returned as secrets, just like the values substituted from
+/// environment variables into `headers` and `token` fields. Other values taken from the environment,
+/// such as an interval or a host name, are not, as they would be redacted from every log line.
+/// # Arguments
+///     * `file` - The path of the configuration file, for errors.
+///     * `text` - The text of the configuration file.
+///     * `lookup` - Looks up an environment variable.
+/// # Errors
+///     Returns an error if the file is not valid YAML, or an error pointing at the reference if a
+///     variable is not set, a file cannot be read, or a value contains a line break.
+pub fn interpolate(
+    file: &str,
+    text: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<Interpolated, ConfigError> {
+    let mut value: Value =
+        serde_path_to_error::deserialize(serde_yaml::Deserializer::from_str(text))
+            .map_err(|e| ConfigError::parse(file, e))?;
+
+    let mut secrets = Vec::new();
+    let rewritten = resolve_value(&mut value, false, &lookup, &mut secrets).map_err(
+        |(reference, message)| ConfigError::Interpolation {
+            file: file.to_string(),
+            location: locate(text, &reference),
+            message,
+        },
+    )?;
+
+    let text = match rewritten {
+        true => serde_yaml::to_string(&value).map_err(|e| ConfigError::Interpolation {
+            file: file.to_string(),
+            location: None,
+            message: e.to_string(),
+        })?,
+        false => text.to_string(),
+    };
+    Ok(Interpolated {
+        text,
+        rewritten,
+        secrets,
+    })
+}
+
+/// An unresolvable reference, e.g. `${UNSET}`, with the reason it cannot be resolved.
+type ReferenceError = (String, String);
+
+/// Resolves the references in the strings of a YAML value and its children.
+/// # Arguments
+///     * `value` - The value to resolve the references of.
+///     * `secret_field` - Whether the value is part of a field holding credentials, see `SECRET_FIELDS`.
+///     * `lookup` - Looks up an environment variable.
+///     * `secrets` - The secrets substituted so far, to which the secrets of the value are added.
+/// # Returns
+///     Whether the value was changed.
+fn resolve_value(
+    value: &mut Value,
+    secret_field: bool,
+    lookup: &impl Fn(&str) -> Option<String>,
+    secrets: &mut Vec<String>,
+) -> Result<bool, ReferenceError> {
+    match value {
+        Value::String(string) => {
+            let Some(resolved) = resolve_string(string, secret_field, lookup, secrets)? else {
+                return Ok(false);
+            };
+            *value = match is_single_reference(string) {
+                true => typed_scalar(resolved),
+                false => Value::String(resolved),
+            };
+            Ok(true)
+        }
+        Value::Sequence(sequence) => {
+            let mut changed = false;
+            for item in sequence {
+                changed |= resolve_value(item, secret_field, lookup, secrets)?;
+            }
+            Ok(changed)
+        }
+        Value::Mapping(mapping) => {
+            let mut changed = false;
+            let mut resolved = Mapping::with_capacity(mapping.len());
+            for (mut key, mut item) in std::mem::take(mapping) {
+                if let Value::String(name) = &key {
+                    if let Some(name) = resolve_string(name, false, lookup, secrets)? {
+                        key = Value::String(name);
+                        changed = true;
+                    }
+                }
+                let secret_field = secret_field
+                    || key
+                        .as_str()
+                        .is_some_and(|name| SECRET_FIELDS.contains(&name));
+                changed |= resolve_value(&mut item, secret_field, lookup, secrets)?;
+                resolved.insert(key, item);
+            }
+            *mapping = resolved;
+            Ok(changed)
+        }
+        Value::Tagged(tagged) => resolve_value(&mut tagged.value, secret_field, lookup, secrets),
+        Value::Null | Value::Bool(_) | Value::Number(_) => Ok(false),
+    }
+}
+
+/// Resolves the references in a string, adding the values of files, and in a field holding
+/// credentials the values of environment variables as well, to the secrets.
+/// # Returns
+///     The resolved string, or `None` if it has no references to resolve.
+fn resolve_string(
+    string: &str,
+    secret_field: bool,
+    lookup: &impl Fn(&str) -> Option<String>,
+    secrets: &mut Vec<String>,
+) -> Result<Option<String>, ReferenceError> {
+    if !string.contains("${") {
+        return Ok(None);
+    }
+
+    let mut resolved = String::with_capacity(string.len());
+    let mut rest = string;
+    while let Some(position) = rest.find('$') {
+        resolved.push_str(&rest[..position]);
+        let reference = &rest[position..];
+
+        if let Some(after) = reference.strip_prefix("$${") {
+            resolved.push_str("${");
+            rest = after;
+            continue;
+        }
+        let Some(after) = reference.strip_prefix("${") else {
+            resolved.push('$');
+            rest = &reference[1..];
+            continue;
+        };
+        let Some(end) = after.find('}') else {
+            return Err((
+                reference.to_string(),
+                "unterminated reference, expected '}'".to_string(),
+            ));
+        };
+
+        let expression = &after[..end];
+        let error = |message: String| (format!("${{{expression}}}"), message);
+        let (value, substituted) = resolve(expression, lookup).map_err(error)?;
+        if value.contains('\n') {
+            return Err(error(format!(
+                "the value of ${{{expression}}} contains a line break"
+            )));
+        }
+        let secret = substituted && (secret_field || expression.starts_with("file:"));
+        if secret && !value.is_empty() {
+            secrets.push(value.clone());
+        }
+        resolved.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    resolved.push_str(rest);
+    Ok(Some(resolved))
+}
+
+/// Resolves the expression of a reference, the part between `${` and `}`.
+/// # Returns
+///     The value, and whether it was substituted from the environment or a file: defaults are part
+///     of the file, so they can never be secret.
+fn resolve(
+    expression: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<(String, bool), String> {
+    if let Some(path) = expression.strip_prefix("file:") {
+        let contents = std::fs::read_to_string(path.trim())
+            .map_err(|e| format!("failed to read {}: {e}", path.trim()))?;
+        return Ok((contents.trim_end_matches(['\r', '\n']).to_string(), true));
+    }
+
+    let (name, default) = match expression.split_once(":-") {
+        Some((name, default)) => (name, Some(default)),
+        None => (expression, None),
+    };
+    if !is_variable_name(name) {
+        return Err(format!("'{name}' is not a valid environment variable name"));
+    }
+    match (lookup(name), default) {
+        (Some(value), Some(default)) if value.is_empty() => Ok((default.to_string(), false)),
+        (Some(value), _) => Ok((value, true)),
+        (None, Some(default)) => Ok((default.to_string(), false)),
+        (None, None) => Err(format!("environment variable {name} is not set")),
+    }
+}
+
+/// Whether a string consists of a single reference, e.g. `${INTERVAL}`.
+fn is_single_reference(string: &str) -> bool {
+    string
+        .strip_prefix("${")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .is_some_and(|expression| !expression.contains('}'))
+}
+
+/// Types a substituted value as YAML would type it as a plain scalar, e.g. `30` as a number or an
+/// empty value as null. Anything but a scalar stays a string.
+fn typed_scalar(value: String) -> Value {
+    match serde_yaml::from_str::<Value>(&value) {
+        Ok(typed @ (Value::Null | Value::Bool(_) | Value::Number(_))) => typed,
+        _ => Value::String(value),
+    }
+}
+
+/// Finds the line and column of a reference in the text of a configuration file, skipping comment
+/// lines and escaped references.
+fn locate(text: &str, reference: &str) -> Option<(usize, usize)> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim_start().starts_with('#'))
+        .find_map(|(index, line)| {
+            line.match_indices(reference)
+                .find(|(position, _)| !line[..*position].ends_with('$'))
+                .map(|(position, _)| (index + 1, line[..position].chars().count() + 1))
+        })
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The secrets substituted into the probe configuration, redacted from every log line and dump.
+/// Secrets of earlier configurations are kept, as they may still show up in probes in flight.
+#[derive(Default)]
+pub struct Secrets {
+    /// The secrets, longest first, so a secret containing another is redacted as a whole.
+    values: RwLock<Vec<String>>,
+}
+
+/// The secrets of the probe configuration, shared with the logger.
+pub static SECRETS: LazyLock<Secrets> = LazyLock::new(Secrets::default);
+
+impl Secrets {
+    /// Adds secrets to redact.
+    pub fn add(&self, secrets: impl IntoIterator<Item = String>) {
+        let mut values = self.values.write().unwrap();
+        for secret in secrets {
+            if !values.contains(&secret) {
+                values.push(secret);
+            }
+        }
+        values.sort_by_key(|secret| Reverse(secret.len()));
+    }
+
+    /// Replaces every secret in a text by `<redacted>`.
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let values = self.values.read().unwrap();
+        if !values.iter().any(|secret| text.contains(secret.as_str())) {
+            return Cow::Borrowed(text);
+        }
+        let mut text = text.to_string();
+        for secret in values.iter() {
+            text = text.replace(secret.as_str(), REDACTED);
+        }
+        Cow::Owned(text)
+    }
+}
+
+/// Serializes a secret setting, e.g. a token, as `<redacted>` when it is set.
+pub fn serialize_redacted<S: Serializer>(
+    value: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    value.as_ref().map(|_| REDACTED).serialize(serializer)
+}
+
+/// Serializes headers with the values redacted, as they usually carry credentials.
+pub fn serialize_redacted_values<S: Serializer>(
+    headers: &BTreeMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(headers.keys().map(|name| (name, REDACTED)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "API_KEY" => Some("s3cr3t".to_string()),
+            "INTERVAL" => Some("30".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let path = std::env::temp_dir().join(format!("oxybox-secret-{}", std::process::id()));
+        std::fs::write(&path, "t0k3n\n").unwrap();
+        let text = format!(
+            "# ${{UNSET}} in a comment\n\
+             url: https://example.com/health?key=${{API_KEY}}&token=${{file:{}}} # ${{UNSET}}\n\
+             polling_interval_seconds: ${{INTERVAL}}\n\
+             organisation_id: ${{INTERVAL}}-${{EMPTY:-ams}}\n\
+             token: ${{EMPTY}}\n\
+             region: ${{REGION:-eu-west}} $$5 $${{literal}}\n",
+            path.display()
+        );
+
+        let interpolated = interpolate("config.yml", &text, lookup).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(interpolated.rewritten);
+        assert_eq!(
+            interpolated.text,
+            "url: https://example.com/health?key=s3cr3t&token=t0k3n\n\
+             polling_interval_seconds: 30\n\
+             organisation_id: 30-ams\n\
+             token: null\n\
+             region: eu-west $$5 ${literal}\n"
+        );
+        assert_eq!(interpolated.secrets, vec!["t0k3n"]);
+
+        let text = "headers:\n  authorization: Bearer ${API_KEY}\n  ${REGION:-x-region}: ${INTERVAL}\n\
+                    sinks:\n  - token: ${API_KEY}\n    host: ${INTERVAL}\n";
+        let interpolated = interpolate("config.yml", text, lookup).unwrap();
+        assert_eq!(interpolated.secrets, vec!["s3cr3t", "30", "s3cr3t"]);
+
+        let plain = "a: 1 # ${UNSET}\n";
+        let interpolated = interpolate("config.yml", plain, lookup).unwrap();
+        assert!(!interpolated.rewritten);
+        assert_eq!(interpolated.text, plain);
+
+        let error = interpolate("config.yml", "a: $${UNSET}\nurl: x${UNSET}\n", lookup)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "config.yml:2:7: environment variable UNSET is not set"
+        );
+        assert!(interpolate("config.yml", "url: ${API_KEY", lookup).is_err());
+        assert!(interpolate("config.yml", "url: ${file:/nonexistent}", lookup).is_err());
+    }
+
+    #[test]
+    fn test_interpolate_keeps_structure() {
+        let lookup = |name: &str| match name {
+            "INJECTED" => Some("x\"\nheaders: {a: b}".to_string()),
+            "MAPPING" => Some("{a: b}".to_string()),
+            _ => None,
+        };
+        assert!(interpolate("config.yml", "url: ${INJECTED}\n", lookup).is_err());
+
+        let interpolated = interpolate("config.yml", "url: ${MAPPING}\n", lookup).unwrap();
+        let value: Value = serde_yaml::from_str(&interpolated.text).unwrap();
+        assert_eq!(value["url"], Value::String("{a: b}".to_string()));
+    }
+
+    #[test]
+    fn test_redact() {
+        let secrets = Secrets::default();
+        assert_eq!(secrets.redact("key=s3cr3t"), "key=s3cr3t");
+        secrets.add(["s3cr3t".to_string(), "s3cr3t-2".to_string()]);
+        assert_eq!(
+            secrets.redact("key=s3cr3t-2&other=s3cr3t"),
+            "key=<redacted>&other=<redacted>"
+        );
+    }
+}
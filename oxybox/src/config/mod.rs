@@ -1,2 +1,8 @@
 pub mod app_config;
+pub mod error;
+pub mod interpolate;
 pub mod probe_config;
+pub mod reload;
+pub mod settings;
+pub mod sources;
+pub mod validate;
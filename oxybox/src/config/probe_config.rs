@@ -1,42 +1,473 @@
-use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use url::Url;
+
+use crate::config::interpolate::serialize_redacted_values;
+use crate::config::validate::validate_label_name;
+use crate::http_probe::schedule::{CronSchedule, ProbeSchedule};
+use crate::mimir::relabel::RelabelConfig;
+use crate::mimir::{BLACKBOX_JOB, HTTP_MODULE};
+use crate::otlp::client::OtlpEndpoint;
+use crate::sink::file::FileSinkConfig;
+use crate::sink::influxdb::InfluxDbConfig;
+use crate::sink::mimir::MimirSinkConfig;
 
 /// An organisation configuration for the OxyBox service.
 /// Contains the organisation ID, the polling interval in seconds, and a list of target configurations.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct OrganisationConfig {
     /// The organisation ID for which this configuration applies.
     /// This translates to the 'Org-Id' header in the Mimir requests.
     pub organisation_id: String,
 
     /// The polling interval in seconds for the OxyBox service.
+    /// Targets are probed at this interval unless they set an interval or schedule of their own.
     pub polling_interval_seconds: u64,
 
     /// A list of target configurations for the OxyBox service.
     pub targets: Vec<TargetConfig>,
+
+    /// The value of the `job` label of the series of the organisation. Defaults to `oxybox`.
+    #[serde(default = "default_job_name")]
+    pub job_name: String,
+
+    /// What the `instance` label of a target is set to. Defaults to its URL.
+    #[serde(default)]
+    pub instance_label: InstanceLabel,
+
+    /// A prefix for the names of the metrics of the organisation, e.g. `oxybox_` to push
+    /// `oxybox_probe_success`. Defaults to none, like the Blackbox Exporter.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub metric_prefix: String,
+
+    /// Labels added to every series of the organisation. Targets may override them.
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "deserialize_labels"
+    )]
+    pub labels: BTreeMap<String, String>,
+
+    /// The settings every target of the organisation inherits, unless it sets them itself.
+    #[serde(default)]
+    pub defaults: TargetDefaults,
+
+    /// Relabeling rules for the labels of every target, such as `instance`, with meta labels
+    /// derived from the URL of the target.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relabel_configs: Vec<RelabelConfig>,
+
+    /// Relabeling rules for every series, applied after `relabel_configs`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub metric_relabel_configs: Vec<RelabelConfig>,
+
+    /// When set, the phase durations of every target are accumulated into native histograms,
+    /// which are pushed once per window.
+    #[serde(default)]
+    pub native_histograms: Option<NativeHistogramsConfig>,
+
+    /// The sinks the probe metrics of the organisation are written to, a single sink or a list.
+    /// Defaults to the Mimir endpoint, using Prometheus remote write.
+    /// `exporter` is accepted as an alias.
+    #[serde(
+        default = "default_sinks",
+        alias = "exporter",
+        deserialize_with = "one_or_many"
+    )]
+    pub sinks: Vec<SinkConfig>,
+}
+
+/// A sink the probe metrics of an organisation are written to.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    /// Push the metrics to the Mimir endpoint using Prometheus remote write.
+    Mimir(MimirSinkConfig),
+
+    /// Export the metrics to an OpenTelemetry collector as OTLP gauges.
+    Otlp(OtlpEndpoint),
+
+    /// Write the metrics as newline delimited JSON to stdout.
+    Stdout,
+
+    /// Write the metrics as newline delimited JSON to a file, rotated by size.
+    File(FileSinkConfig),
+
+    /// Write the metrics to InfluxDB using line protocol.
+    Influxdb(InfluxDbConfig),
+}
+
+fn default_job_name() -> String {
+    BLACKBOX_JOB.to_string()
+}
+
+/// What the `instance` label of a target is set to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceLabel {
+    /// The URL of the target, e.g. `https://www.example.com/health`.
+    #[default]
+    Url,
+
+    /// The host of the URL of the target, e.g. `www.example.com`.
+    Host,
+
+    /// The `name` of the target, e.g. `Website`.
+    Name,
+}
+
+impl InstanceLabel {
+    /// Returns the value of the `instance` label of a target.
+    /// Falls back to the URL when the URL has no host or the target has no name.
+    pub fn value<'a>(&self, target: &'a TargetConfig) -> Cow<'a, str> {
+        let fallback = Cow::Borrowed(target.url.as_str());
+        match self {
+            InstanceLabel::Url => fallback,
+            InstanceLabel::Host => Url::parse(&target.url)
+                .ok()
+                .and_then(|url| url.host_str().map(|host| Cow::Owned(host.to_string())))
+                .unwrap_or(fallback),
+            InstanceLabel::Name => target
+                .name
+                .as_deref()
+                .map(Cow::Borrowed)
+                .unwrap_or(fallback),
+        }
+    }
+}
+
+fn default_sinks() -> Vec<SinkConfig> {
+    vec![SinkConfig::Mimir(MimirSinkConfig::default())]
+}
+
+/// Deserializes either a single value or a list of values into a vector.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// Deserializes the labels of a target or organisation, rejecting names which are not valid
+/// Prometheus label names or are reserved for the labels Oxybox sets itself.
+fn deserialize_labels<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let labels = BTreeMap::<String, String>::deserialize(deserializer)?;
+    for name in labels.keys() {
+        validate_label_name(name).map_err(de::Error::custom)?;
+    }
+    Ok(labels)
+}
+
+/// Configuration for pushing the phase durations as Prometheus native histograms.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct NativeHistogramsConfig {
+    /// The interval in seconds at which the histograms are pushed.
+    #[serde(default = "default_histogram_window_seconds")]
+    pub window_seconds: u64,
+
+    /// The bucket schema, from -4 (coarsest) to 8 (finest).
+    /// Defaults to 3, which has 8 buckets per power of two, for an error of at most ~4.4%.
+    #[serde(default = "default_histogram_schema")]
+    pub schema: i32,
+
+    /// Whether to keep pushing the `probe_http_duration_seconds` gauges of every probe as well.
+    /// Defaults to false, as the histograms replace them.
+    #[serde(default)]
+    pub keep_phase_gauges: bool,
+}
+
+fn default_histogram_window_seconds() -> u64 {
+    60
+}
+
+fn default_histogram_schema() -> i32 {
+    3
 }
 
 /// A target configuration for the OxyBox service.
-/// Contains the target URL and a list of accepted HTTP status codes.
-#[derive(Debug, Clone, Deserialize)]
+/// Contains the target URL and the settings of its probe. Settings which are not set are inherited
+/// from the `defaults` of the organisation, and then from the `defaults` of the configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 pub struct TargetConfig {
     /// The URL of the target service to be monitored.
     pub url: String,
 
+    /// A friendly name of the target, used as its `instance` label with `instance_label: name`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
     /// The accepted HTTP status codes for the target service.
     /// Defaults to 200 if not specified.
-    #[serde(default = "default_status_codes")]
-    pub accepted_status_codes: Vec<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accepted_status_codes: Option<Vec<u16>>,
+
+    /// Headers sent with the probe request, e.g. for authentication.
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        serialize_with = "serialize_redacted_values"
+    )]
+    pub headers: BTreeMap<String, String>,
+
+    /// How long the probe may take. The HTTP request times out after 5 seconds otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+
+    /// Labels added to every series of the target, e.g. `team` or `severity` to route alerts.
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "deserialize_labels"
+    )]
+    pub labels: BTreeMap<String, String>,
+
+    /// How the certificate of the target is verified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+
+    /// The value of the `module` label of the series of the target. Defaults to `http_probe`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+
+    /// How often the target is probed. Defaults to the polling interval of the organisation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_seconds: Option<u64>,
+
+    /// A cron expression of when the target is probed, instead of an interval.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<CronSchedule>,
+}
+
+impl TargetConfig {
+    /// The accepted HTTP status codes, 200 if not specified.
+    pub fn accepted_status_codes(&self) -> Vec<u16> {
+        self.accepted_status_codes
+            .clone()
+            .unwrap_or_else(default_status_codes)
+    }
+
+    /// The TLS settings, accepting any certificate if not specified.
+    pub fn tls(&self) -> TlsConfig {
+        self.tls.clone().unwrap_or_default()
+    }
+
+    /// The value of the `module` label, `http_probe` if not specified.
+    pub fn module(&self) -> &str {
+        self.module.as_deref().unwrap_or(HTTP_MODULE)
+    }
+
+    /// When the target is probed: at its cron schedule, or every interval.
+    /// # Arguments
+    ///     * `polling_interval_seconds` - The polling interval of the organisation, used when the
+    ///       target sets neither an interval nor a schedule.
+    pub fn probe_schedule(&self, polling_interval_seconds: u64) -> ProbeSchedule {
+        match (&self.schedule, self.interval_seconds) {
+            (Some(schedule), _) => ProbeSchedule::Cron(Box::new(schedule.clone())),
+            (None, interval) => ProbeSchedule::Interval(Duration::from_secs(
+                interval.unwrap_or(polling_interval_seconds),
+            )),
+        }
+    }
+
+    /// Takes the settings the target does not set itself from the defaults.
+    /// The headers and labels are merged, the target winning when both set the same one.
+    /// An interval and a schedule are taken together, so a target with either keeps its own timing.
+    pub fn inherit(&mut self, defaults: &TargetDefaults) {
+        if self.interval_seconds.is_none() && self.schedule.is_none() {
+            self.interval_seconds = defaults.interval_seconds;
+            self.schedule = defaults.schedule.clone();
+        }
+        if self.accepted_status_codes.is_none() {
+            self.accepted_status_codes = defaults.accepted_status_codes.clone();
+        }
+        for (name, value) in &defaults.headers {
+            self.headers
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+        if self.timeout_seconds.is_none() {
+            self.timeout_seconds = defaults.timeout_seconds;
+        }
+        for (name, value) in &defaults.labels {
+            self.labels
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+        if self.tls.is_none() {
+            self.tls = defaults.tls.clone();
+        }
+        if self.module.is_none() {
+            self.module = defaults.module.clone();
+        }
+    }
 }
 
 fn default_status_codes() -> Vec<u16> {
     vec![200]
 }
 
+/// The settings of a target which can be set once for all targets of an organisation or a file.
+/// See `TargetConfig` for their meaning. Unknown fields are rejected, so an organisation named
+/// `defaults` is not silently taken for the top-level defaults.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetDefaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accepted_status_codes: Option<Vec<u16>>,
+
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        serialize_with = "serialize_redacted_values"
+    )]
+    pub headers: BTreeMap<String, String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "deserialize_labels"
+    )]
+    pub labels: BTreeMap<String, String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_seconds: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<CronSchedule>,
+}
+
+/// How the certificate of a target is verified.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TlsConfig {
+    /// Whether to accept invalid certificates, such as expired or self-signed ones.
+    /// Defaults to true, so the expiry of any certificate can be monitored.
+    #[serde(default = "default_insecure_skip_verify")]
+    pub insecure_skip_verify: bool,
+
+    /// A PEM file with the CA certificates to verify the certificate with, next to the system roots.
+    #[serde(default)]
+    pub ca_file: Option<PathBuf>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            insecure_skip_verify: default_insecure_skip_verify(),
+            ca_file: None,
+        }
+    }
+}
+
+fn default_insecure_skip_verify() -> bool {
+    true
+}
+
 pub type Config = std::collections::HashMap<String, OrganisationConfig>;
 
+/// The key of the `defaults` block of a configuration file, which is not an organisation.
+pub const DEFAULTS_KEY: &str = "defaults";
+
+/// A probe configuration file: its organisations, keyed by tenant name, and the `defaults` of the
+/// targets of all organisations. When the configuration is split across files, the `defaults` of a
+/// file apply to the organisations of every file.
+#[derive(Debug, Default)]
+pub struct ConfigFile {
+    pub defaults: Option<TargetDefaults>,
+    pub organisations: Config,
+}
+
+impl ConfigFile {
+    /// Applies the defaults to every target: the `defaults` and `labels` of its organisation first,
+    /// then the top-level `defaults`.
+    pub fn into_config(self) -> Config {
+        let mut config = self.organisations;
+        for org_config in config.values_mut() {
+            let org_labels = TargetDefaults {
+                labels: org_config.labels.clone(),
+                ..Default::default()
+            };
+            for target in &mut org_config.targets {
+                target.inherit(&org_config.defaults);
+                target.inherit(&org_labels);
+                if let Some(defaults) = &self.defaults {
+                    target.inherit(defaults);
+                }
+            }
+        }
+        config
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfigFile {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ConfigFileVisitor;
+
+        impl<'de> Visitor<'de> for ConfigFileVisitor {
+            type Value = ConfigFile;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of organisations")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<ConfigFile, A::Error> {
+                let mut file = ConfigFile::default();
+                while let Some(name) = map.next_key::<String>()? {
+                    if name == DEFAULTS_KEY {
+                        file.defaults = Some(map.next_value()?);
+                        continue;
+                    }
+                    let org_config = map.next_value()?;
+                    if file.organisations.contains_key(&name) {
+                        return Err(de::Error::custom(format!(
+                            "organisation {name} is defined twice"
+                        )));
+                    }
+                    file.organisations.insert(name, org_config);
+                }
+                Ok(file)
+            }
+        }
+
+        deserializer.deserialize_map(ConfigFileVisitor)
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::mimir::client::RemoteWriteVersion;
 
     #[test]
     fn test_default_status_codes() {
@@ -71,7 +502,10 @@ pub mod test {
         assert_eq!(demo_config.targets.len(), 2);
         assert_eq!(demo_config.targets[0].url, "https://www.google.com");
         assert_eq!(demo_config.targets[1].url, "https://www.github.com");
-        assert_eq!(demo_config.targets[1].accepted_status_codes, vec![200, 301]);
+        assert_eq!(
+            demo_config.targets[1].accepted_status_codes(),
+            vec![200, 301]
+        );
         let org_x_config = config
             .get("organisationX")
             .expect("OrganisationX config not found");
@@ -80,6 +514,204 @@ pub mod test {
         assert_eq!(org_x_config.targets.len(), 1);
         assert_eq!(org_x_config.targets[0].url, "http://www.example.com");
         // check default status codes
-        assert_eq!(org_x_config.targets[0].accepted_status_codes, vec![200]);
+        assert_eq!(org_x_config.targets[0].accepted_status_codes(), vec![200]);
+    }
+
+    #[test]
+    fn test_native_histograms_deserialization() {
+        let yaml = r#"
+                    demo:
+                        organisation_id: demo
+                        polling_interval_seconds: 10
+                        native_histograms:
+                            window_seconds: 300
+                        targets:
+                            - url: https://www.google.com
+                    other:
+                        organisation_id: other
+                        polling_interval_seconds: 10
+                        targets: []
+                                    "#;
+
+        let config: Config = serde_yaml::from_str(yaml).expect("Invalid YAML");
+        let histograms = config["demo"]
+            .native_histograms
+            .as_ref()
+            .expect("Native histograms not enabled");
+        assert_eq!(histograms.window_seconds, 300);
+        assert_eq!(histograms.schema, 3);
+        assert!(!histograms.keep_phase_gauges);
+        assert!(config["other"].native_histograms.is_none());
+    }
+
+    #[test]
+    fn test_sinks_deserialization() {
+        let yaml = r#"
+                    demo:
+                        organisation_id: demo
+                        polling_interval_seconds: 10
+                        exporter:
+                            type: otlp
+                            endpoint: http://otel-collector:4317
+                            protocol: grpc
+                        targets: []
+                    other:
+                        organisation_id: other
+                        polling_interval_seconds: 10
+                        targets: []
+                    debug:
+                        organisation_id: debug
+                        polling_interval_seconds: 10
+                        sinks:
+                            - type: mimir
+                              url: http://mimir-eu:9009
+                              remote_write_version: 2
+                            - type: stdout
+                            - type: file
+                              path: /tmp/oxybox.jsonl
+                            - type: influxdb
+                              url: http://localhost:8086
+                              bucket: probes
+                        targets: []
+                                    "#;
+
+        let config: Config = serde_yaml::from_str(yaml).expect("Invalid YAML");
+        let [SinkConfig::Otlp(endpoint)] = config["demo"].sinks.as_slice() else {
+            panic!("Expected the OTLP sink");
+        };
+        assert_eq!(endpoint.url, "http://otel-collector:4317");
+        assert!(matches!(
+            config["other"].sinks.as_slice(),
+            [SinkConfig::Mimir(_)]
+        ));
+        let [
+            SinkConfig::Mimir(mimir),
+            SinkConfig::Stdout,
+            SinkConfig::File(file),
+            SinkConfig::Influxdb(influxdb),
+        ] = config["debug"].sinks.as_slice()
+        else {
+            panic!("Expected all sinks");
+        };
+        assert_eq!(mimir.url.as_deref(), Some("http://mimir-eu:9009"));
+        assert_eq!(mimir.remote_write_version, Some(RemoteWriteVersion::V2));
+        assert_eq!(file.max_files, 5);
+        assert_eq!(influxdb.bucket, "probes");
+    }
+
+    #[test]
+    fn test_defaults_inheritance() {
+        let yaml = r#"
+                    defaults:
+                        timeout_seconds: 5
+                        headers:
+                            user-agent: oxybox
+                        labels:
+                            env: production
+                    demo:
+                        organisation_id: demo
+                        polling_interval_seconds: 10
+                        labels:
+                            service: website
+                        defaults:
+                            accepted_status_codes: [200, 204]
+                            labels:
+                                team: platform
+                            tls:
+                                insecure_skip_verify: false
+                        targets:
+                            - url: https://www.example.com
+                            - url: https://api.example.com
+                              accepted_status_codes: [401]
+                              timeout_seconds: 2
+                              labels:
+                                  env: staging
+                              module: http_2xx
+                                    "#;
+
+        let config = serde_yaml::from_str::<ConfigFile>(yaml)
+            .expect("Invalid YAML")
+            .into_config();
+        assert_eq!(config.len(), 1);
+        let [inherited, overridden] = config["demo"].targets.as_slice() else {
+            panic!("Expected two targets");
+        };
+
+        assert_eq!(inherited.accepted_status_codes(), vec![200, 204]);
+        assert_eq!(inherited.timeout_seconds, Some(5));
+        assert_eq!(inherited.headers["user-agent"], "oxybox");
+        assert_eq!(inherited.labels["env"], "production");
+        assert_eq!(inherited.labels["team"], "platform");
+        assert_eq!(inherited.labels["service"], "website");
+        assert!(!inherited.tls().insecure_skip_verify);
+        assert_eq!(inherited.module(), "http_probe");
+
+        assert_eq!(overridden.accepted_status_codes(), vec![401]);
+        assert_eq!(overridden.timeout_seconds, Some(2));
+        assert_eq!(overridden.labels["env"], "staging");
+        assert_eq!(overridden.labels["team"], "platform");
+        assert_eq!(overridden.module(), "http_2xx");
+    }
+
+    #[test]
+    fn test_defaults_is_not_an_organisation() {
+        let yaml = r#"
+                    defaults:
+                        organisation_id: demo
+                        polling_interval_seconds: 10
+                        targets:
+                            - url: https://www.example.com
+                                    "#;
+
+        let error = serde_yaml::from_str::<ConfigFile>(yaml).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("unknown field `organisation_id`"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_invalid_labels() {
+        let yaml = r#"
+                    demo:
+                        organisation_id: demo
+                        polling_interval_seconds: 10
+                        targets:
+                            - url: https://www.example.com
+                              labels:
+                                  job: website
+                                    "#;
+
+        let error = serde_yaml::from_str::<Config>(yaml).err().unwrap();
+        assert!(
+            error
+                .to_string()
+                .contains("'job' is reserved, as Oxybox sets it itself")
+        );
+    }
+
+    #[test]
+    fn test_instance_label() {
+        let target = TargetConfig {
+            url: "https://www.example.com/health".to_string(),
+            name: Some("Website".to_string()),
+            ..Default::default()
+        };
+        let unnamed = TargetConfig {
+            url: "https://www.example.com/health".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            InstanceLabel::Url.value(&target),
+            "https://www.example.com/health"
+        );
+        assert_eq!(InstanceLabel::Host.value(&target), "www.example.com");
+        assert_eq!(InstanceLabel::Name.value(&target), "Website");
+        assert_eq!(
+            InstanceLabel::Name.value(&unnamed),
+            "https://www.example.com/health"
+        );
     }
 }
@@ -0,0 +1,82 @@
+use std::time::{Duration, SystemTime};
+
+use super::sources::fingerprint;
+
+/// Watches the probe configuration files, by polling their modification times and, on Unix, listening
+/// for SIGHUP. Polling also picks up Kubernetes ConfigMap updates, which swap the files via a symlink,
+/// and files being added to or removed from a configuration directory.
+pub struct ConfigWatcher {
+    path: String,
+    poll_interval: Duration,
+    fingerprint: Vec<(String, Option<SystemTime>)>,
+    hangup: Hangup,
+}
+
+#[cfg(unix)]
+type Hangup = tokio::signal::unix::Signal;
+
+#[cfg(not(unix))]
+type Hangup = ();
+
+impl ConfigWatcher {
+    /// Creates a watcher for the configuration files, as they are now.
+    /// # Arguments
+    ///     * `path` - The path of the probe configuration file or directory, or a glob pattern.
+    ///     * `poll_interval` - How often to check the file for changes, zero to only reload on SIGHUP.
+    pub fn new(path: &str, poll_interval: Duration) -> Self {
+        ConfigWatcher {
+            path: path.to_string(),
+            poll_interval,
+            fingerprint: fingerprint(path),
+            hangup: listen_for_hangup(),
+        }
+    }
+
+    /// Waits until a configuration file was modified, added or removed, or SIGHUP was received.
+    pub async fn changed(&mut self) {
+        loop {
+            tokio::select! {
+                _ = poll(self.poll_interval) => {
+                    let fingerprint = fingerprint(&self.path);
+                    if fingerprint != self.fingerprint {
+                        log::info!("Detected a change of {}", self.path);
+                        self.fingerprint = fingerprint;
+                        return;
+                    }
+                }
+                _ = hangup(&mut self.hangup) => {
+                    log::info!("Received SIGHUP");
+                    self.fingerprint = fingerprint(&self.path);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Sleeps for the poll interval, or forever when polling is disabled.
+async fn poll(interval: Duration) {
+    match interval.is_zero() {
+        true => std::future::pending().await,
+        false => tokio::time::sleep(interval).await,
+    }
+}
+
+#[cfg(unix)]
+fn listen_for_hangup() -> Hangup {
+    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .expect("Failed to listen for SIGHUP")
+}
+
+#[cfg(not(unix))]
+fn listen_for_hangup() -> Hangup {}
+
+#[cfg(unix)]
+async fn hangup(signal: &mut Hangup) {
+    signal.recv().await;
+}
+
+#[cfg(not(unix))]
+async fn hangup(_: &mut Hangup) {
+    std::future::pending().await
+}
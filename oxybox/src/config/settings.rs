@@ -0,0 +1,135 @@
+use std::net::SocketAddr;
+
+use clap::{ArgAction, Args};
+use serde::Serialize;
+
+use crate::config::interpolate::serialize_redacted;
+use crate::http_probe::result::SampleTimestamp;
+use crate::mimir::client::RemoteWriteVersion;
+use crate::otlp::client::OtlpProtocol;
+
+/// The settings of Oxybox, taken from the command-line flags or else from the environment variables.
+/// Variables in a `.env` file only apply when they are not set in the environment.
+/// Serialized with the environment variable names, for `oxybox print-config`.
+#[derive(Debug, Clone, Args, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub struct Settings {
+    /// The probe configuration file.
+    #[arg(long, env = "CONFIG_FILE", default_value = "config.yml")]
+    pub config_file: String,
+
+    /// How often to check the probe configuration file for changes, 0 to only reload on SIGHUP.
+    #[arg(long, env = "CONFIG_RELOAD_INTERVAL_SECONDS", default_value_t = 10)]
+    pub config_reload_interval_seconds: u64,
+
+    /// The DNS servers to resolve targets with, as comma separated IP addresses.
+    #[arg(
+        long,
+        env = "DNS_HOSTS",
+        value_delimiter = ',',
+        value_parser = trimmed,
+        default_value = "1.1.1.1,8.8.8.8"
+    )]
+    pub dns_hosts: Vec<String>,
+
+    /// Labels added to every pushed series, e.g. `region=eu-west,probe_location=ams`.
+    #[arg(long, env = "EXTERNAL_LABELS")]
+    pub external_labels: Option<String>,
+
+    /// The HA cluster this instance belongs to, for Mimir's HA tracker.
+    #[arg(long, env = "HA_CLUSTER", requires = "ha_replica")]
+    pub ha_cluster: Option<String>,
+
+    /// The replica name of this instance within its HA cluster.
+    #[arg(long, env = "HA_REPLICA", requires = "ha_cluster")]
+    pub ha_replica: Option<String>,
+
+    /// The label carrying the HA cluster.
+    #[arg(long, env = "HA_CLUSTER_LABEL", default_value = "cluster")]
+    pub ha_cluster_label: String,
+
+    /// The label carrying the HA replica.
+    #[arg(long, env = "HA_REPLICA_LABEL", default_value = "__replica__")]
+    pub ha_replica_label: String,
+
+    /// The base URL of Mimir.
+    #[arg(long, env = "MIMIR_ENDPOINT", default_value = "http://localhost:9009")]
+    pub mimir_endpoint: String,
+
+    /// The remote write protocol version, 1 or 2.
+    #[arg(long, env = "MIMIR_REMOTE_WRITE_VERSION", default_value = "1")]
+    pub mimir_remote_write_version: RemoteWriteVersion,
+
+    /// How often metric metadata is sent along with a Remote Write 1.0 push.
+    #[arg(
+        long,
+        env = "MIMIR_METADATA_SEND_INTERVAL_SECONDS",
+        default_value_t = 60
+    )]
+    pub mimir_metadata_send_interval_seconds: u64,
+
+    /// Whether staleness markers are pushed for series which disappear.
+    #[arg(long, env = "STALENESS_MARKERS", default_value_t = true, action = ArgAction::Set)]
+    pub staleness_markers: bool,
+
+    /// The moment of a probe used as the timestamp of its samples, probe_start or probe_end.
+    #[arg(long, env = "SAMPLE_TIMESTAMP", default_value = "probe_start")]
+    pub sample_timestamp: SampleTimestamp,
+
+    /// The OpenTelemetry collector to export a trace of every probe to.
+    #[arg(long, env = "OTLP_TRACES_ENDPOINT")]
+    pub otlp_traces_endpoint: Option<String>,
+
+    /// The transport used to export traces, http or grpc.
+    #[arg(long, env = "OTLP_TRACES_PROTOCOL", default_value = "http")]
+    pub otlp_traces_protocol: OtlpProtocol,
+
+    /// Headers sent with every trace export, e.g. `authorization=Bearer <token>`.
+    #[arg(long, env = "OTLP_TRACES_HEADERS", hide_env_values = true)]
+    #[serde(serialize_with = "serialize_redacted")]
+    pub otlp_traces_headers: Option<String>,
+
+    /// The address of the HTTP server exposing the probe results and health endpoints, or off.
+    #[arg(
+        long,
+        env = "LISTEN_ADDRESS",
+        value_parser = optional_address,
+        default_value = "0.0.0.0:9115"
+    )]
+    pub listen_address: OptionalAddress,
+
+    /// The tenant to push the metrics Oxybox keeps about itself to.
+    #[arg(long, env = "ADMIN_TENANT")]
+    pub admin_tenant: Option<String>,
+
+    /// How often the metrics about Oxybox itself are pushed to the admin tenant.
+    #[arg(long, env = "ADMIN_PUSH_INTERVAL_SECONDS", default_value_t = 30)]
+    pub admin_push_interval_seconds: u64,
+
+    /// How long the writes of a tenant may fail for `/readyz` to report ready.
+    #[arg(long, env = "READINESS_MAX_WRITE_AGE_SECONDS", default_value_t = 300)]
+    pub readiness_max_write_age_seconds: u64,
+
+    /// How long to wait for the probes in flight when shutting down.
+    #[arg(long, env = "SHUTDOWN_TIMEOUT_SECONDS", default_value_t = 25)]
+    pub shutdown_timeout_seconds: u64,
+}
+
+/// An address which can be turned off. An alias, as clap would otherwise make the flag optional instead.
+type OptionalAddress = Option<SocketAddr>;
+
+/// Parses an address, or `off` for none.
+fn optional_address(value: &str) -> Result<OptionalAddress, String> {
+    match value.trim() {
+        "off" => Ok(None),
+        address => address
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("'{address}' is not a valid address or 'off': {e}")),
+    }
+}
+
+/// Parses a value of a comma separated list, ignoring the whitespace around it.
+fn trimmed(value: &str) -> Result<String, String> {
+    Ok(value.trim().to_string())
+}
@@ -0,0 +1,121 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use glob::MatchOptions;
+
+use super::error::{ConfigError, ConfigProblem};
+
+/// The extensions of the files read from a configuration directory.
+const EXTENSIONS: &[&str] = &["yml", "yaml"];
+
+/// Resolves `CONFIG_FILE` to the probe configuration files:
+///     * A file is read on its own.
+///     * A directory contributes its `.yml` and `.yaml` files, ignoring hidden files such as the
+///       `..data` directory of a Kubernetes ConfigMap mount.
+///     * A glob pattern, such as `conf.d/*.yml`, contributes the files it matches.
+/// # Arguments
+///     * `path` - The path of the file or directory, or the glob pattern.
+/// # Returns
+///     The configuration files, sorted by path.
+/// # Errors
+///     Returns an error if the directory cannot be read, the pattern is invalid, or there are no files.
+pub fn config_files(path: &str) -> Result<Vec<String>, ConfigError> {
+    let pattern = path.contains(['*', '?', '[']);
+    let files = if pattern {
+        let options = MatchOptions {
+            require_literal_leading_dot: true,
+            ..MatchOptions::new()
+        };
+        let paths = glob::glob_with(path, options).map_err(|e| {
+            ConfigError::Environment(vec![ConfigProblem::new(
+                "CONFIG_FILE",
+                format!("'{path}' is not a valid pattern: {e}"),
+            )])
+        })?;
+        let mut files: Vec<String> = paths
+            .filter_map(Result::ok)
+            .filter(|file| file.is_file())
+            .map(|file| file.to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        files
+    } else if Path::new(path).is_dir() {
+        let entries = std::fs::read_dir(path).map_err(|source| ConfigError::Read {
+            file: path.to_string(),
+            source,
+        })?;
+        let mut files: Vec<String> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|file| is_config_file(file))
+            .map(|file| file.to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        files
+    } else {
+        return Ok(vec![path.to_string()]);
+    };
+
+    if files.is_empty() {
+        let message = match pattern {
+            true => format!("'{path}' matches no files"),
+            false => format!("'{path}' contains no .yml or .yaml files"),
+        };
+        return Err(ConfigError::Environment(vec![ConfigProblem::new(
+            "CONFIG_FILE",
+            message,
+        )]));
+    }
+    Ok(files)
+}
+
+/// Whether a directory entry is a visible YAML file.
+fn is_config_file(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    let yaml = path
+        .extension()
+        .is_some_and(|extension| EXTENSIONS.iter().any(|e| extension == *e));
+    !hidden && yaml && path.is_file()
+}
+
+/// Returns the probe configuration files with their modification times, to detect changes.
+/// Files being added or removed change the result as well.
+pub fn fingerprint(path: &str) -> Vec<(String, Option<SystemTime>)> {
+    config_files(path)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|file| {
+            let modified = std::fs::metadata(&file).and_then(|m| m.modified()).ok();
+            (file, modified)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_files() {
+        let dir = std::env::temp_dir().join(format!("oxybox-conf.d-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("..data")).unwrap();
+        for file in ["b.yaml", "a.yml", "README.md", ".hidden.yml"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        let dir_str = dir.to_str().unwrap();
+
+        let files = config_files(dir_str).unwrap();
+        let pattern = config_files(&format!("{dir_str}/*.yml")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            files,
+            vec![format!("{dir_str}/a.yml"), format!("{dir_str}/b.yaml")]
+        );
+        assert_eq!(pattern, vec![format!("{dir_str}/a.yml")]);
+        assert_eq!(config_files("config.yml").unwrap(), vec!["config.yml"]);
+        assert!(config_files(&format!("{dir_str}/*.yml")).is_err());
+    }
+}
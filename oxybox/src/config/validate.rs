@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use reqwest::header::{HeaderName, HeaderValue};
+use url::Url;
+
+use crate::mimir::RESERVED_LABELS;
+use crate::mimir::relabel::{RelabelAction, RelabelConfig, is_label_name};
+
+use super::error::ConfigProblem;
+use super::probe_config::{Config, InstanceLabel, OrganisationConfig, SinkConfig, TargetConfig};
+
+/// Checks a parsed probe configuration for values which would make probing fail, such as targets
+/// with an invalid URL. All problems are collected, so they can be fixed in one go.
+/// # Arguments
+///     * `config` - The parsed probe configuration.
+/// # Returns
+///     The problems found with the tenant they were found in, so they can be attributed to the file
+///     defining the tenant. Ordered by tenant, empty if the configuration is valid.
+pub fn validate(config: &Config) -> Vec<(&String, ConfigProblem)> {
+    let mut problems = Vec::new();
+    let mut tenants: Vec<(&String, &OrganisationConfig)> = config.iter().collect();
+    tenants.sort_by_key(|(name, _)| *name);
+
+    let mut organisation_ids: HashMap<&str, &str> = HashMap::new();
+    for (name, org_config) in tenants {
+        let mut tenant_problems = Vec::new();
+        if let Some(other) = organisation_ids.insert(&org_config.organisation_id, name) {
+            tenant_problems.push(ConfigProblem::new(
+                format!("{name}.organisation_id"),
+                format!(
+                    "organisation ID '{}' is also used by {other}",
+                    org_config.organisation_id
+                ),
+            ));
+        }
+        validate_organisation(name, org_config, &mut tenant_problems);
+        problems.extend(tenant_problems.into_iter().map(|problem| (name, problem)));
+    }
+    problems
+}
+
+fn validate_organisation(
+    name: &str,
+    org_config: &OrganisationConfig,
+    problems: &mut Vec<ConfigProblem>,
+) {
+    if org_config.organisation_id.trim().is_empty() {
+        problems.push(ConfigProblem::new(
+            format!("{name}.organisation_id"),
+            "must not be empty",
+        ));
+    }
+    if org_config.polling_interval_seconds == 0 {
+        problems.push(ConfigProblem::new(
+            format!("{name}.polling_interval_seconds"),
+            "must be at least 1",
+        ));
+    }
+    if org_config.targets.is_empty() {
+        problems.push(ConfigProblem::new(
+            format!("{name}.targets"),
+            "must contain at least one target",
+        ));
+    }
+
+    for (i, target) in org_config.targets.iter().enumerate() {
+        let path = format!("{name}.targets[{i}]");
+        if let Err(message) = validate_url(&target.url) {
+            problems.push(ConfigProblem::new(format!("{path}.url"), message));
+        }
+        validate_target(&path, target, problems);
+    }
+
+    if let Some(histograms) = &org_config.native_histograms {
+        if histograms.window_seconds == 0 {
+            problems.push(ConfigProblem::new(
+                format!("{name}.native_histograms.window_seconds"),
+                "must be at least 1",
+            ));
+        }
+        if !(-4..=8).contains(&histograms.schema) {
+            problems.push(ConfigProblem::new(
+                format!("{name}.native_histograms.schema"),
+                format!("{} is out of range, expected -4 to 8", histograms.schema),
+            ));
+        }
+    }
+
+    validate_naming(name, org_config, problems);
+
+    for (key, configs) in [
+        ("relabel_configs", &org_config.relabel_configs),
+        ("metric_relabel_configs", &org_config.metric_relabel_configs),
+    ] {
+        for (i, config) in configs.iter().enumerate() {
+            validate_relabel_config(&format!("{name}.{key}[{i}]"), config, problems);
+        }
+    }
+
+    for (i, sink) in org_config.sinks.iter().enumerate() {
+        let path = format!("{name}.sinks[{i}]");
+        match sink {
+            SinkConfig::Otlp(endpoint) => {
+                if let Err(message) = validate_url(&endpoint.url) {
+                    problems.push(ConfigProblem::new(format!("{path}.endpoint"), message));
+                }
+            }
+            SinkConfig::Mimir(config) => {
+                if let Some(Err(message)) = config.url.as_deref().map(validate_url) {
+                    problems.push(ConfigProblem::new(format!("{path}.url"), message));
+                }
+            }
+            SinkConfig::Influxdb(config) => {
+                if let Err(message) = validate_url(&config.url) {
+                    problems.push(ConfigProblem::new(format!("{path}.url"), message));
+                }
+            }
+            SinkConfig::File(config) if config.path.as_os_str().is_empty() => {
+                problems.push(ConfigProblem::new(
+                    format!("{path}.path"),
+                    "must not be empty",
+                ));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Checks the settings of a target, including those it inherited from the defaults.
+fn validate_target(path: &str, target: &TargetConfig, problems: &mut Vec<ConfigProblem>) {
+    let accepted_status_codes = target.accepted_status_codes();
+    if accepted_status_codes.is_empty() {
+        problems.push(ConfigProblem::new(
+            format!("{path}.accepted_status_codes"),
+            "must contain at least one status code",
+        ));
+    }
+    for code in &accepted_status_codes {
+        if !(100..=599).contains(code) {
+            problems.push(ConfigProblem::new(
+                format!("{path}.accepted_status_codes"),
+                format!("{code} is not an HTTP status code, expected 100 to 599"),
+            ));
+        }
+    }
+    for (name, value) in &target.headers {
+        if HeaderName::from_bytes(name.as_bytes()).is_err() {
+            problems.push(ConfigProblem::new(
+                format!("{path}.headers"),
+                format!("'{name}' is not a valid header name"),
+            ));
+        } else if HeaderValue::from_str(value).is_err() {
+            problems.push(ConfigProblem::new(
+                format!("{path}.headers.{name}"),
+                "is not a valid header value",
+            ));
+        }
+    }
+    if target.timeout_seconds == Some(0) {
+        problems.push(ConfigProblem::new(
+            format!("{path}.timeout_seconds"),
+            "must be at least 1",
+        ));
+    }
+    if target.interval_seconds == Some(0) {
+        problems.push(ConfigProblem::new(
+            format!("{path}.interval_seconds"),
+            "must be at least 1",
+        ));
+    }
+    if target.interval_seconds.is_some() && target.schedule.is_some() {
+        problems.push(ConfigProblem::new(
+            format!("{path}.schedule"),
+            "cannot be combined with interval_seconds, set only one of them",
+        ));
+    }
+    if target.module.as_ref().is_some_and(|m| m.trim().is_empty()) {
+        problems.push(ConfigProblem::new(
+            format!("{path}.module"),
+            "must not be empty",
+        ));
+    }
+    if let Some(ca_file) = target.tls.as_ref().and_then(|tls| tls.ca_file.as_ref()) {
+        if !ca_file.is_file() {
+            problems.push(ConfigProblem::new(
+                format!("{path}.tls.ca_file"),
+                format!("{} does not exist", ca_file.display()),
+            ));
+        }
+    }
+}
+
+/// Checks the job name, instance labels and metric prefix of an organisation.
+/// Targets must not share a URL, as the state of every target, such as its tracked series, is keyed by
+/// its URL. With `instance_label: host` or `name`, targets must not end up with the same instance
+/// either, as their series would collide.
+fn validate_naming(name: &str, org_config: &OrganisationConfig, problems: &mut Vec<ConfigProblem>) {
+    if org_config.job_name.trim().is_empty() {
+        problems.push(ConfigProblem::new(
+            format!("{name}.job_name"),
+            "must not be empty",
+        ));
+    }
+    if !org_config.metric_prefix.is_empty() && !is_metric_prefix(&org_config.metric_prefix) {
+        problems.push(ConfigProblem::new(
+            format!("{name}.metric_prefix"),
+            format!(
+                "'{}' is not a valid metric name prefix, expected letters, digits, underscores and colons, not starting with a digit",
+                org_config.metric_prefix
+            ),
+        ));
+    }
+
+    let instance_label = org_config.instance_label;
+    let mut urls: HashMap<&str, usize> = HashMap::new();
+    let mut instances: HashMap<String, usize> = HashMap::new();
+    for (i, target) in org_config.targets.iter().enumerate() {
+        let path = format!("{name}.targets[{i}]");
+        if let Some(other) = urls.insert(target.url.as_str(), i) {
+            problems.push(ConfigProblem::new(
+                format!("{path}.url"),
+                format!("has the same URL as {name}.targets[{other}]"),
+            ));
+            continue;
+        }
+        if instance_label == InstanceLabel::Name
+            && target.name.as_ref().is_none_or(|n| n.trim().is_empty())
+        {
+            problems.push(ConfigProblem::new(
+                format!("{path}.name"),
+                "is required, as the organisation uses instance_label: name",
+            ));
+            continue;
+        }
+        if instance_label == InstanceLabel::Url {
+            continue;
+        }
+        let instance = instance_label.value(target).into_owned();
+        if let Some(other) = instances.insert(instance.clone(), i) {
+            problems.push(ConfigProblem::new(
+                path,
+                format!("has the same instance '{instance}' as {name}.targets[{other}]"),
+            ));
+        }
+    }
+}
+
+fn is_metric_prefix(prefix: &str) -> bool {
+    let mut chars = prefix.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Checks that a relabeling rule has the fields its action needs.
+fn validate_relabel_config(path: &str, config: &RelabelConfig, problems: &mut Vec<ConfigProblem>) {
+    for source_label in &config.source_labels {
+        if !is_label_name(source_label) {
+            problems.push(ConfigProblem::new(
+                format!("{path}.source_labels"),
+                format!("'{source_label}' is not a valid label name"),
+            ));
+        }
+    }
+
+    let needs_target_label = matches!(
+        config.action,
+        RelabelAction::Replace | RelabelAction::Hashmod | RelabelAction::Lowercase
+    );
+    match &config.target_label {
+        None if needs_target_label => problems.push(ConfigProblem::new(
+            format!("{path}.target_label"),
+            format!("is required for the {} action", config.action),
+        )),
+        // the target label of replace may refer to groups of the regex, e.g. `${1}`
+        Some(target_label)
+            if needs_target_label
+                && !is_label_name(target_label)
+                && !(config.action == RelabelAction::Replace && target_label.contains('$')) =>
+        {
+            problems.push(ConfigProblem::new(
+                format!("{path}.target_label"),
+                format!("'{target_label}' is not a valid label name"),
+            ));
+        }
+        _ => {}
+    }
+
+    if config.action == RelabelAction::Hashmod && config.modulus.unwrap_or(0) == 0 {
+        problems.push(ConfigProblem::new(
+            format!("{path}.modulus"),
+            "must be at least 1 for the hashmod action",
+        ));
+    }
+}
+
+/// Checks that a label name is a valid Prometheus label name, and not one of the labels Oxybox sets
+/// itself or a name reserved for internal use, starting with `__`.
+/// # Arguments
+///     * `name` - The label name.
+/// # Errors
+///     Returns a message describing why the name cannot be used.
+pub fn validate_label_name(name: &str) -> Result<(), String> {
+    if !is_label_name(name) {
+        return Err(format!(
+            "'{name}' is not a valid label name, expected letters, digits and underscores, not starting with a digit"
+        ));
+    }
+    if name.starts_with("__") {
+        return Err(format!(
+            "'{name}' is reserved, label names starting with __ are for internal use"
+        ));
+    }
+    if RESERVED_LABELS.contains(&name) {
+        return Err(format!("'{name}' is reserved, as Oxybox sets it itself"));
+    }
+    Ok(())
+}
+
+/// Checks that a URL parses and uses the `http` or `https` scheme.
+fn validate_url(url: &str) -> Result<(), String> {
+    let parsed = Url::parse(url).map_err(|e| format!("'{url}' is not a valid URL: {e}"))?;
+    match parsed.scheme() {
+        "http" | "https" if parsed.host_str().is_some() => Ok(()),
+        "http" | "https" => Err(format!("'{url}' has no host")),
+        scheme => Err(format!(
+            "'{url}' uses the unsupported scheme '{scheme}', expected http or https"
+        )),
+    }
+}
+
+/// Checks that every DNS host is an IP address.
+/// # Arguments
+///     * `dns_hosts` - The DNS hosts, as configured in `DNS_HOSTS`.
+/// # Returns
+///     The problems found, empty if all hosts are IP addresses.
+pub fn validate_dns_hosts(dns_hosts: &[String]) -> Vec<ConfigProblem> {
+    dns_hosts
+        .iter()
+        .filter(|host| host.parse::<IpAddr>().is_err())
+        .map(|host| ConfigProblem::new("DNS_HOSTS", format!("'{host}' is not an IP address")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let yaml = r#"
+                    demo:
+                        organisation_id: shared
+                        polling_interval_seconds: 0
+                        targets:
+                            - url: https://www.example.com
+                            - url: example.com
+                              accepted_status_codes: [200, 42]
+                            - url: https://api.example.com
+                              timeout_seconds: 0
+                              interval_seconds: 0
+                              schedule: "*/5 * * * *"
+                              headers:
+                                  "bad header": value
+                              tls:
+                                  ca_file: /nonexistent/ca.pem
+                            - url: https://www.example.com
+                              headers:
+                                  authorization: Bearer token
+                    named:
+                        organisation_id: named
+                        polling_interval_seconds: 10
+                        job_name: blackbox
+                        instance_label: host
+                        metric_prefix: 1x_
+                        targets:
+                            - url: https://www.example.com/a
+                            - url: https://www.example.com/b
+                    other:
+                        organisation_id: shared
+                        polling_interval_seconds: 10
+                        targets: []
+                        relabel_configs:
+                            - source_labels: [instance]
+                              action: lowercase
+                        metric_relabel_configs:
+                            - source_labels: [__name__]
+                              target_label: shard
+                              action: hashmod
+                                    "#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+
+        let problems: Vec<String> = validate(&config)
+            .iter()
+            .map(|(_, problem)| problem.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "demo.polling_interval_seconds: must be at least 1",
+                "demo.targets[1].url: 'example.com' is not a valid URL: relative URL without a base",
+                "demo.targets[1].accepted_status_codes: 42 is not an HTTP status code, expected 100 to 599",
+                "demo.targets[2].headers: 'bad header' is not a valid header name",
+                "demo.targets[2].timeout_seconds: must be at least 1",
+                "demo.targets[2].interval_seconds: must be at least 1",
+                "demo.targets[2].schedule: cannot be combined with interval_seconds, set only one of them",
+                "demo.targets[2].tls.ca_file: /nonexistent/ca.pem does not exist",
+                "demo.targets[3].url: has the same URL as demo.targets[0]",
+                "named.metric_prefix: '1x_' is not a valid metric name prefix, expected letters, digits, underscores and colons, not starting with a digit",
+                "named.targets[1]: has the same instance 'www.example.com' as named.targets[0]",
+                "other.organisation_id: organisation ID 'shared' is also used by demo",
+                "other.targets: must contain at least one target",
+                "other.relabel_configs[0].target_label: is required for the lowercase action",
+                "other.metric_relabel_configs[0].modulus: must be at least 1 for the hashmod action",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_label_name() {
+        assert!(validate_label_name("team").is_ok());
+        assert!(validate_label_name("_env2").is_ok());
+        assert!(validate_label_name("2team").is_err());
+        assert!(validate_label_name("team-name").is_err());
+        assert!(validate_label_name("").is_err());
+        assert!(validate_label_name("__name__").is_err());
+        assert!(validate_label_name("instance").is_err());
+        assert!(validate_label_name("tenant").is_err());
+    }
+
+    #[test]
+    fn test_validate_dns_hosts() {
+        let hosts = vec!["1.1.1.1".to_string(), "dns.google".to_string()];
+        assert_eq!(
+            validate_dns_hosts(&hosts),
+            vec![ConfigProblem::new(
+                "DNS_HOSTS",
+                "'dns.google' is not an IP address"
+            )]
+        );
+    }
+}
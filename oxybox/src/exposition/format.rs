@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::mimir::client::prometheus::prompb::{
+    MetricMetadata, TimeSeries, metric_metadata::MetricType,
+};
+use crate::mimir::metadata::find_metadata;
+use crate::mimir::staleness::is_stale_marker;
+
+const NAME_LABEL: &str = "__name__";
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The text format the series are exposed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpositionFormat {
+    /// The Prometheus text exposition format, version 0.0.4.
+    Prometheus,
+    /// The OpenMetrics text format, version 1.0.0.
+    OpenMetrics,
+}
+
+impl ExpositionFormat {
+    /// Selects the format from the `Accept` header of a scrape, preferring OpenMetrics when accepted.
+    pub fn from_accept(accept: Option<&str>) -> Self {
+        match accept {
+            Some(accept) if accept.contains("application/openmetrics-text") => {
+                ExpositionFormat::OpenMetrics
+            }
+            _ => ExpositionFormat::Prometheus,
+        }
+    }
+
+    /// The `Content-Type` of a response in this format.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExpositionFormat::Prometheus => PROMETHEUS_CONTENT_TYPE,
+            ExpositionFormat::OpenMetrics => OPENMETRICS_CONTENT_TYPE,
+        }
+    }
+}
+
+/// Renders series in a text exposition format, grouped by metric family with their HELP and TYPE.
+/// Only the latest sample of every series is rendered, without timestamp, just like a scrape of the
+/// Blackbox Exporter. Staleness markers and native histograms, which have no text representation,
+/// are left out.
+/// # Arguments
+///     * `metrics` - The series to render, e.g. the ones built by `create_probe_metrics`.
+///     * `format` - The format to render the series in.
+///     * `metadata` - The HELP, TYPE and UNIT of the metric families, e.g. `probe_metadata()`.
+/// # Returns
+///     The exposition, ready to be served to a scraper.
+pub fn render(
+    metrics: &[TimeSeries],
+    format: ExpositionFormat,
+    metadata: &[MetricMetadata],
+) -> String {
+    let mut families: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for series in metrics {
+        let Some(sample) = series.samples.last() else {
+            continue;
+        };
+        if is_stale_marker(sample.value) {
+            continue;
+        }
+        let Some(name) = series.labels.iter().find(|label| label.name == NAME_LABEL) else {
+            continue;
+        };
+
+        let mut labels: Vec<_> = series
+            .labels
+            .iter()
+            .filter(|label| label.name != NAME_LABEL)
+            .collect();
+        labels.sort_by(|a, b| a.name.cmp(&b.name));
+        let labels = labels
+            .iter()
+            .map(|label| format!("{}=\"{}\"", label.name, escape_label_value(&label.value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        let labels = match labels.is_empty() {
+            true => String::new(),
+            false => format!("{{{labels}}}"),
+        };
+
+        families
+            .entry(name.value.as_str())
+            .or_default()
+            .push(format!(
+                "{}{labels} {}",
+                name.value,
+                format_value(sample.value)
+            ));
+    }
+
+    let mut exposition = String::new();
+    for (name, mut lines) in families {
+        if let Some(metadata) = find_metadata(metadata, name) {
+            let _ = writeln!(exposition, "# HELP {name} {}", metadata.help);
+            let _ = writeln!(exposition, "# TYPE {name} {}", type_name(metadata.r#type));
+            if format == ExpositionFormat::OpenMetrics && !metadata.unit.is_empty() {
+                let _ = writeln!(exposition, "# UNIT {name} {}", metadata.unit);
+            }
+        }
+        lines.sort();
+        for line in lines {
+            exposition.push_str(&line);
+            exposition.push('\n');
+        }
+    }
+    if format == ExpositionFormat::OpenMetrics {
+        exposition.push_str("# EOF\n");
+    }
+    exposition
+}
+
+fn type_name(metric_type: i32) -> &'static str {
+    match MetricType::try_from(metric_type) {
+        Ok(MetricType::Counter) => "counter",
+        Ok(MetricType::Gauge) => "gauge",
+        Ok(MetricType::Histogram) => "histogram",
+        Ok(MetricType::Summary) => "summary",
+        _ => "unknown",
+    }
+}
+
+fn format_value(value: f64) -> String {
+    match value {
+        v if v.is_nan() => "NaN".to_string(),
+        v if v == f64::INFINITY => "+Inf".to_string(),
+        v if v == f64::NEG_INFINITY => "-Inf".to_string(),
+        v => v.to_string(),
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mimir::client::create_time_series;
+    use crate::mimir::metadata::probe_metadata;
+    use crate::mimir::staleness::STALE_NAN;
+
+    #[test]
+    fn test_render() {
+        let labels = [
+            ("target", "https://example.com/\"quoted\""),
+            ("job", "oxybox"),
+        ];
+        let metrics = vec![
+            create_time_series("probe_success", &labels, 1.0, Some(1_000)),
+            create_time_series("probe_duration_seconds", &labels, 0.25, Some(1_000)),
+            create_time_series("probe_http_status_code", &labels, STALE_NAN, Some(1_000)),
+        ];
+
+        let prometheus = render(&metrics, ExpositionFormat::Prometheus, &probe_metadata());
+        assert_eq!(
+            prometheus,
+            "# HELP probe_duration_seconds Returns how long the probe took to complete in seconds\n\
+             # TYPE probe_duration_seconds gauge\n\
+             probe_duration_seconds{job=\"oxybox\",target=\"https://example.com/\\\"quoted\\\"\"} 0.25\n\
+             # HELP probe_success Displays whether or not the probe was a success\n\
+             # TYPE probe_success gauge\n\
+             probe_success{job=\"oxybox\",target=\"https://example.com/\\\"quoted\\\"\"} 1\n"
+        );
+
+        let unlabelled = vec![create_time_series("probe_success", &[], 0.0, None)];
+        assert_eq!(
+            render(&unlabelled, ExpositionFormat::Prometheus, &[]),
+            "probe_success 0\n"
+        );
+
+        let openmetrics = render(&metrics, ExpositionFormat::OpenMetrics, &probe_metadata());
+        assert!(openmetrics.contains("# UNIT probe_duration_seconds seconds\n"));
+        assert!(openmetrics.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_format_from_accept() {
+        assert_eq!(
+            ExpositionFormat::from_accept(Some(
+                "application/openmetrics-text;version=1.0.0,text/plain;q=0.5"
+            )),
+            ExpositionFormat::OpenMetrics
+        );
+        assert_eq!(
+            ExpositionFormat::from_accept(Some("text/plain")),
+            ExpositionFormat::Prometheus
+        );
+        assert_eq!(
+            ExpositionFormat::from_accept(None),
+            ExpositionFormat::Prometheus
+        );
+    }
+}
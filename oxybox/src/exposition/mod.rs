@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::mimir::TENANT_LABEL;
+use crate::mimir::client::prometheus::prompb::{Label, MetricMetadata, TimeSeries};
+use crate::mimir::staleness::is_stale_marker;
+
+pub mod format;
+
+/// The latest series of a tenant, keyed by their labels, with the metadata of their metric families.
+struct TenantSeries {
+    org_id: String,
+    series: HashMap<Vec<Label>, TimeSeries>,
+    metadata: Vec<MetricMetadata>,
+}
+
+/// Holds the latest pushed series of every tenant, so they can be scraped from the `/metrics`
+/// endpoint. The series are the same as the ones written to the sinks, so both paths stay identical.
+#[derive(Default)]
+pub struct LatestSeries {
+    tenants: Mutex<HashMap<String, TenantSeries>>,
+}
+
+impl LatestSeries {
+    /// Records the series pushed for a tenant, replacing the previous sample of every series.
+    /// Series with a staleness marker are removed, as they are no longer written.
+    /// # Arguments
+    ///     * `tenant` - The tenant name.
+    ///     * `org_id` - The organisation ID of the tenant.
+    ///     * `metrics` - The pushed series.
+    ///     * `metadata` - The metadata of the metric families, carrying the metric prefix of the
+    ///       tenant. Empty metadata keeps the metadata of the previous push.
+    pub fn update(
+        &self,
+        tenant: &str,
+        org_id: &str,
+        metrics: &[TimeSeries],
+        metadata: &[MetricMetadata],
+    ) {
+        let mut tenants = self.tenants.lock().unwrap();
+        let tenant_series = tenants
+            .entry(tenant.to_string())
+            .or_insert_with(|| TenantSeries {
+                org_id: org_id.to_string(),
+                series: HashMap::new(),
+                metadata: vec![],
+            });
+        if !metadata.is_empty() {
+            tenant_series.metadata = metadata.to_vec();
+        }
+
+        for series in metrics {
+            let stale = series
+                .samples
+                .last()
+                .is_some_and(|sample| is_stale_marker(sample.value));
+            match stale {
+                true => tenant_series.series.remove(&series.labels),
+                false => tenant_series
+                    .series
+                    .insert(series.labels.clone(), series.clone()),
+            };
+        }
+    }
+
+    /// Forgets all series of a tenant, when it is no longer probed.
+    pub fn remove(&self, tenant: &str) {
+        self.tenants.lock().unwrap().remove(tenant);
+    }
+
+    /// Returns the latest series of a tenant, or of all tenants when `tenant` is `None`.
+    /// When returning all tenants, a `tenant` label holding the organisation ID is set on every
+    /// series, as different tenants may probe the same targets. It replaces a `tenant` label the
+    /// series may already have, e.g. from an external label or relabeling rule.
+    /// # Arguments
+    ///     * `tenant` - The tenant name or organisation ID to return the series of.
+    /// # Returns
+    ///     The series and the metadata of their metric families, or `None` if the tenant is unknown.
+    pub fn snapshot(&self, tenant: Option<&str>) -> Option<(Vec<TimeSeries>, Vec<MetricMetadata>)> {
+        let tenants = self.tenants.lock().unwrap();
+        match tenant {
+            Some(tenant) => tenants
+                .iter()
+                .find(|(name, series)| *name == tenant || series.org_id == tenant)
+                .map(|(_, series)| {
+                    (
+                        series.series.values().cloned().collect(),
+                        series.metadata.clone(),
+                    )
+                }),
+            None => Some((
+                tenants
+                    .values()
+                    .flat_map(|tenant_series| {
+                        tenant_series.series.values().map(|series| {
+                            let mut series = series.clone();
+                            series.labels.retain(|label| label.name != TENANT_LABEL);
+                            series.labels.push(Label {
+                                name: TENANT_LABEL.to_string(),
+                                value: tenant_series.org_id.clone(),
+                            });
+                            series
+                        })
+                    })
+                    .collect(),
+                merged_metadata(tenants.values()),
+            )),
+        }
+    }
+}
+
+/// Merges the metadata of several tenants, keeping the first metadata of every metric family, as
+/// tenants without a metric prefix share the same metric families.
+fn merged_metadata<'a>(tenants: impl Iterator<Item = &'a TenantSeries>) -> Vec<MetricMetadata> {
+    let mut merged: Vec<MetricMetadata> = vec![];
+    for metadata in tenants.flat_map(|tenant_series| &tenant_series.metadata) {
+        if !merged
+            .iter()
+            .any(|m| m.metric_family_name == metadata.metric_family_name)
+        {
+            merged.push(metadata.clone());
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mimir::client::create_time_series;
+    use crate::mimir::metadata::{find_metadata, prefixed_probe_metadata};
+    use crate::mimir::staleness::STALE_NAN;
+
+    #[test]
+    fn test_latest_series() {
+        let latest = LatestSeries::default();
+        let labels = [("instance", "https://example.com"), ("tenant", "relabeled")];
+        latest.update(
+            "Tenant",
+            "org-1",
+            &[
+                create_time_series("probe_success", &labels, 0.0, Some(1_000)),
+                create_time_series("probe_http_status_code", &labels, 500.0, Some(1_000)),
+            ],
+            &prefixed_probe_metadata(""),
+        );
+        latest.update(
+            "Tenant",
+            "org-1",
+            &[
+                create_time_series("probe_success", &labels, 1.0, Some(2_000)),
+                create_time_series("probe_http_status_code", &labels, STALE_NAN, Some(2_000)),
+            ],
+            &[],
+        );
+
+        let (series, metadata) = latest.snapshot(Some("org-1")).unwrap();
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].samples[0].value, 1.0);
+        assert_eq!(metadata, prefixed_probe_metadata(""));
+        assert!(latest.snapshot(Some("Tenant")).is_some());
+        assert!(latest.snapshot(Some("unknown")).is_none());
+
+        let (all, _) = latest.snapshot(None).unwrap();
+        let tenants: Vec<&str> = all[0]
+            .labels
+            .iter()
+            .filter(|l| l.name == "tenant")
+            .map(|l| l.value.as_str())
+            .collect();
+        assert_eq!(tenants, vec!["org-1"]);
+
+        latest.update(
+            "Acme",
+            "acme",
+            &[create_time_series(
+                "acme_probe_success",
+                &labels,
+                1.0,
+                Some(2_000),
+            )],
+            &prefixed_probe_metadata("acme_"),
+        );
+        let (_, metadata) = latest.snapshot(None).unwrap();
+        for name in ["probe_success", "acme_probe_success"] {
+            assert!(
+                find_metadata(&metadata, name).is_some(),
+                "{name} has no metadata"
+            );
+        }
+        assert!(find_metadata(&prefixed_probe_metadata("acme_"), "probe_success").is_none());
+    }
+}
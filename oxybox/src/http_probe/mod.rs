@@ -1,5 +1,8 @@
 pub mod probe;
 pub mod result;
+pub mod schedule;
+pub mod supervisor;
+pub mod trace;
 
 use std::fmt::Write;
 
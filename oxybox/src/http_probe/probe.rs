@@ -1,7 +1,12 @@
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use chrono::Utc;
 use reqwest::Client;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
 use tokio::time::sleep;
 use trust_dns_resolver::name_server::GenericConnector;
 use url::Url;
@@ -9,14 +14,28 @@ use url::Url;
 use tokio_native_tls::TlsConnector as TokioTlsConnector;
 use x509_parser::parse_x509_certificate;
 
-use crate::config::probe_config::{OrganisationConfig, TargetConfig};
+use crate::config::probe_config::{OrganisationConfig, TargetConfig, TlsConfig};
 use crate::http_probe::report;
-use crate::mimir::client::send_to_mimir;
-use crate::mimir::create_probe_metrics;
+use crate::mimir::client::prometheus::prompb::{MetricMetadata, TimeSeries};
+use crate::mimir::client::{ExternalLabels, MimirEndpoint};
+use crate::mimir::histogram::PhaseHistograms;
+use crate::mimir::metadata::prefixed_probe_metadata;
+use crate::mimir::staleness::SeriesTracker;
+use crate::mimir::{create_probe_metrics, label_series, remove_phase_duration_gauges};
+use crate::otlp::client::{OtlpEndpoint, TRACES, export};
+use crate::otlp::trace::create_probe_trace;
+use crate::self_monitoring::health::HEALTH;
+use crate::self_monitoring::{ProbeOutcome, SELF_METRICS};
+use crate::sink::{MetricsBatch, MetricsSinks};
 
 use trust_dns_resolver::{AsyncResolver, TokioAsyncResolver};
 
-use super::result::ProbeResult;
+use super::result::{ProbeResult, SampleTimestamp};
+use super::trace::TraceContext;
+
+/// The key under which the native histogram series of a tenant are tracked for staleness.
+/// Histograms of removed targets vanish from the next push and are marked stale as a result.
+pub const NATIVE_HISTOGRAMS_KEY: &str = "native_histograms";
 
 /// Struct to hold the results of an HTTP probe.
 /// This struct contains various metrics related to the HTTP request, such as DNS resolution time, connection time, TLS handshake time, HTTP status code, and more.
@@ -152,6 +171,8 @@ fn convert_http_version(version: reqwest::Version) -> f64 {
 ///     * `connector` - An instance of `TokioTlsConnector` for establishing TLS connections.
 ///     * `resolver` - An instance of `AsyncResolver` for DNS resolution.
 ///     * `url` - The URL to probe, which should be a valid HTTP or HTTPS URL.
+///     * `headers` - The headers to send with the probe request.
+///     * `trace_context` - The trace context injected into the probe request as `traceparent` header.
 /// # Returns
 ///     A `Result` containing a `ProbeResult` struct with the probe metrics if successful, or an error message if the probe fails.
 /// # Errors
@@ -163,8 +184,11 @@ async fn probe_url(
         GenericConnector<trust_dns_resolver::name_server::TokioRuntimeProvider>,
     >,
     url: &str,
+    headers: &BTreeMap<String, String>,
+    trace_context: &TraceContext,
 ) -> Result<ProbeResult, String> {
     let probe_start = Instant::now();
+    let started_at_ms = Utc::now().timestamp_millis();
     let url = url.to_string();
 
     let parsed_url = Url::parse(&url).ok();
@@ -178,7 +202,14 @@ async fn probe_url(
         get_connect_timings(&host, connector, resolver, url.starts_with("https://")).await?;
     // Measure HTTP probe
     let start = Instant::now();
-    let status_result = client.get(&url).send().await;
+    let mut request = client.get(&url);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    let status_result = request
+        .header("traceparent", trace_context.traceparent())
+        .send()
+        .await;
     let (processing_time, transfer_time, http_status, http_version) = match status_result {
         Ok(resp) => {
             let time_till_first_byte = start.elapsed().as_secs_f64();
@@ -210,6 +241,7 @@ async fn probe_url(
 
     Ok(ProbeResult {
         url: url.to_string(),
+        started_at_ms,
         dns_time: probe_result.dns_time,
         connect_time: probe_result.connect_time,
         tls_time: probe_result.tls_time,
@@ -219,62 +251,307 @@ async fn probe_url(
         http_version,
         transfer_time,
         total_probe_time,
+        trace_context: trace_context.clone(),
     })
 }
 
+/// Shared resources used by every probe loop, regardless of the tenant.
+#[derive(Clone)]
+pub struct ProbeContext {
+    /// The DNS resolver for resolving hostnames.
+    pub resolver: TokioAsyncResolver,
+
+    /// The TLS connector for establishing secure connections.
+    pub tls_connector: TokioTlsConnector,
+
+    /// The Mimir endpoint to send metrics to.
+    pub mimir_endpoint: MimirEndpoint,
+
+    /// The labels added to every series written to the sinks, including the labels of Mimir's HA
+    /// tracker.
+    pub external_labels: ExternalLabels,
+
+    /// The maximum width for tenant name formatting in logs.
+    pub max_org_width: usize,
+
+    /// Whether staleness markers are pushed for series which disappear.
+    pub staleness_markers: bool,
+
+    /// The moment of a probe used as the timestamp of its samples.
+    pub sample_timestamp: SampleTimestamp,
+
+    /// The OpenTelemetry collector to export the trace of every probe to, if any.
+    pub otlp_traces_endpoint: Option<OtlpEndpoint>,
+}
+
+/// What the probe loop of a tenant is asked to do, sent by the supervisor. The states only go up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LoopControl {
+    /// Keep probing.
+    Run,
+
+    /// Start no new probe, but let the probes in flight complete.
+    Drain,
+
+    /// Cancel the probes in flight and stop right away.
+    Cancel,
+}
+
+/// Waits until the probe loop is asked to stop at least as hard as `control`, or until the
+/// supervisor is gone.
+async fn stop_requested(receiver: &mut watch::Receiver<LoopControl>, control: LoopControl) {
+    let _ = receiver.wait_for(|requested| *requested >= control).await;
+}
+
+/// A tenant, with the state shared by the probes of all its targets.
+pub struct Tenant {
+    /// The tenant name, the key of the organisation in the probe configuration.
+    pub name: String,
+
+    /// The configuration of the organisation.
+    pub org_config: OrganisationConfig,
+
+    /// Tracks the pushed series of the tenant to emit staleness markers.
+    /// Shared with the next loop of the tenant when its configuration is reloaded.
+    pub series_tracker: Arc<SeriesTracker>,
+
+    /// The sinks the metrics of the tenant are written to.
+    /// Shared with the next loop of the tenant when its configuration is reloaded.
+    pub sinks: Arc<MetricsSinks>,
+
+    /// The HTTP client and TLS connector of every target, in the order of the targets, or the
+    /// error setting them up failed with.
+    pub clients: Vec<Result<ProbeClients, String>>,
+}
+
+impl Tenant {
+    /// Creates a tenant, building the HTTP client and TLS connector of each of its targets once.
+    /// A target whose client cannot be built is logged, and fails every probe with the error.
+    /// # Arguments
+    ///     * `name` - The tenant name.
+    ///     * `org_config` - The configuration of the organisation.
+    ///     * `series_tracker` - The series tracker, shared with the previous loop of the tenant.
+    ///     * `sinks` - The sinks, shared with the previous loop of the tenant.
+    ///     * `context` - The shared TLS connector, used by targets without TLS settings of their own.
+    pub fn new(
+        name: String,
+        org_config: OrganisationConfig,
+        series_tracker: Arc<SeriesTracker>,
+        sinks: Arc<MetricsSinks>,
+        context: &ProbeContext,
+    ) -> Self {
+        let clients = org_config
+            .targets
+            .iter()
+            .map(|target| {
+                let clients = ProbeClients::new(target, context);
+                if let Err(e) = &clients {
+                    log::error!("[{name}] Failed to set up the probe of {}: {e}", target.url);
+                }
+                clients
+            })
+            .collect();
+        Tenant {
+            name,
+            org_config,
+            series_tracker,
+            sinks,
+            clients,
+        }
+    }
+
+    /// Writes metrics of the tenant to all of its sinks.
+    /// # Arguments
+    ///     * `metrics` - The series to write.
+    ///     * `metadata` - The metadata of the metric families, may be empty.
+    ///     * `created_timestamps` - The created timestamps of the series, see `MetricsBatch`.
+    pub async fn write(
+        &self,
+        metrics: &[TimeSeries],
+        metadata: &[MetricMetadata],
+        created_timestamps: &[i64],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.sinks
+            .write(&MetricsBatch {
+                tenant: &self.name,
+                org_id: &self.org_config.organisation_id,
+                metrics,
+                metadata,
+                created_timestamps,
+            })
+            .await
+    }
+}
+
+/// Runs the probe loop of a single tenant, probing every target on its own schedule: every interval,
+/// by default the polling interval of the organisation, or at the moments of a cron expression.
+/// Every target is probed by a task of its own, so a slow target does not delay the others.
+/// Metric metadata is attached to every push, the sinks decide themselves how often they send it.
+/// The series pushed for each target are recorded in the series tracker of the tenant, so they can be marked stale.
+/// When native histograms are enabled, the phase durations are pushed as histograms once per window.
+/// The outcome of every probe, the number of targets and how late every probe starts are recorded in
+/// the self-monitoring metrics.
+/// When `control` turns to `Drain`, no new probe is started. The probes in flight complete first,
+/// after which the native histograms are pushed one last time. When it turns to `Cancel`, the probes
+/// in flight are cancelled. The loop only returns once the probes of all its targets have stopped.
 pub async fn run_probe_loop(
-    tenant_name: String,
-    org_config: OrganisationConfig,
-    resolver: TokioAsyncResolver,
-    tls_connector: TokioTlsConnector,
-    mimir_endpoint: String,
-    max_org_width: usize,
+    tenant: Arc<Tenant>,
+    context: ProbeContext,
+    mut control: watch::Receiver<LoopControl>,
 ) {
+    let org_config = &tenant.org_config;
+    let phase_histograms = org_config
+        .native_histograms
+        .as_ref()
+        .map(|config| Arc::new(PhaseHistograms::new(config)));
+    let polling_interval = Duration::from_secs(org_config.polling_interval_seconds);
+    SELF_METRICS.set_targets(&tenant.name, org_config.targets.len());
+
+    let mut target_loops = JoinSet::new();
+    for (target, clients) in org_config.targets.iter().zip(&tenant.clients) {
+        target_loops.spawn(run_target_loop(
+            tenant.clone(),
+            target.clone(),
+            clients.clone(),
+            context.clone(),
+            phase_histograms.clone(),
+            control.clone(),
+        ));
+    }
+
+    let mut last_histogram_push = Instant::now();
     loop {
-        let mut handles = vec![];
+        tokio::select! {
+            _ = sleep(polling_interval) => {}
+            _ = stop_requested(&mut control, LoopControl::Drain) => break,
+        }
 
-        let start_time = Instant::now();
+        if let (Some(config), Some(histograms)) = (&org_config.native_histograms, &phase_histograms)
+        {
+            if last_histogram_push.elapsed() >= Duration::from_secs(config.window_seconds) {
+                last_histogram_push = Instant::now();
+                push_phase_histograms(&tenant, histograms, &context).await;
+            }
+        }
+    }
 
-        for target in &org_config.targets {
-            let connector = tls_connector.clone();
-            let resolver = resolver.clone();
-            let target = target.clone();
-            let tenant_name = tenant_name.clone();
-            let org_id = org_config.organisation_id.clone();
-            let mimir_endpoint = mimir_endpoint.clone();
-
-            let probe_timeout_duration: Duration =
-                Duration::from_secs(org_config.polling_interval_seconds);
-
-            handles.push(tokio::spawn(tokio::time::timeout(
-                probe_timeout_duration,
-                async move {
-                    handle_target_probe(
-                        tenant_name,
-                        &org_id,
-                        &target,
-                        &connector,
-                        &resolver,
-                        &mimir_endpoint,
-                        max_org_width,
-                    )
-                    .await;
-                },
-            )));
+    while let Some(result) = target_loops.join_next().await {
+        if let Err(join_err) = result {
+            SELF_METRICS.record_task_panic(&tenant.name);
+            log::error!("Task panicked: {:?}", join_err);
         }
+    }
+    if *control.borrow() == LoopControl::Cancel {
+        return;
+    }
+    if let Some(histograms) = &phase_histograms {
+        push_phase_histograms(&tenant, histograms, &context).await;
+    }
+}
+
+/// Probes a single target of a tenant on its schedule, until `control` turns to `Drain`.
+/// A probe may take until the next one is due, after which it is cancelled and counted as timed out.
+/// The probe runs in a task owned by the loop, which is cancelled and awaited when `control` turns
+/// to `Cancel`, so no probe outlives the loop.
+/// Every cycle is recorded as a heartbeat of the loop for `/readyz`, together with how late the probe
+/// started in the self-monitoring metrics. Both are forgotten when the loop stops.
+async fn run_target_loop(
+    tenant: Arc<Tenant>,
+    target: TargetConfig,
+    clients: Result<ProbeClients, String>,
+    context: ProbeContext,
+    phase_histograms: Option<Arc<PhaseHistograms>>,
+    mut control: watch::Receiver<LoopControl>,
+) {
+    let polling_interval = Duration::from_secs(tenant.org_config.polling_interval_seconds);
+    let schedule = target.probe_schedule(tenant.org_config.polling_interval_seconds);
+    let target = Arc::new(target);
+    let clients = Arc::new(clients);
+    let mut scheduled_at = None;
 
-        for handle in handles {
-            if let Err(join_err) = handle.await {
+    loop {
+        let now = Utc::now();
+        let Some(wait) = schedule.wait(scheduled_at, now) else {
+            log::warn!(
+                "[{}] The schedule of {} has no moments left, it is no longer probed",
+                tenant.name,
+                target.url
+            );
+            if let Some(histograms) = &phase_histograms {
+                histograms.remove_target(&target.url);
+            }
+            break;
+        };
+        let due = now + wait;
+        let timeout = schedule
+            .wait(Some(due), due)
+            .filter(|timeout| !timeout.is_zero())
+            .unwrap_or(polling_interval);
+        HEALTH.record_loop_cycle(&tenant.name, &target.url, wait + timeout);
+        tokio::select! {
+            _ = sleep(wait) => {}
+            _ = stop_requested(&mut control, LoopControl::Drain) => break,
+        }
+        let lag = (Utc::now() - due).to_std().unwrap_or_default();
+        SELF_METRICS.set_schedule_lag(&tenant.name, &target.url, lag);
+        scheduled_at = Some(due);
+
+        let metadata = prefixed_probe_metadata(&tenant.org_config.metric_prefix);
+        let mut probe = JoinSet::new();
+        probe.spawn({
+            let tenant = tenant.clone();
+            let target = target.clone();
+            let clients = clients.clone();
+            let context = context.clone();
+            let phase_histograms = phase_histograms.clone();
+            tokio::time::timeout(timeout, async move {
+                handle_target_probe(
+                    &tenant,
+                    &target,
+                    clients.as_ref().as_ref().map_err(String::as_str),
+                    &context,
+                    &metadata,
+                    phase_histograms.as_deref(),
+                )
+                .await;
+            })
+        });
+
+        let result = tokio::select! {
+            result = probe.join_next() => result,
+            _ = stop_requested(&mut control, LoopControl::Cancel) => {
+                probe.abort_all();
+                while probe.join_next().await.is_some() {}
+                break;
+            }
+        };
+        match result {
+            None | Some(Ok(Ok(()))) => {}
+            Some(Ok(Err(_))) => SELF_METRICS.record_probe(&tenant.name, ProbeOutcome::Timeout),
+            Some(Err(join_err)) => {
+                SELF_METRICS.record_task_panic(&tenant.name);
                 log::error!("Task panicked: {:?}", join_err);
             }
         }
-        let elapsed = start_time.elapsed().as_secs();
-        let wait = org_config
-            .polling_interval_seconds
-            .checked_sub(elapsed)
-            .unwrap_or(0);
+    }
+    HEALTH.remove_loop(&tenant.name, &target.url);
+    SELF_METRICS.remove_target(&tenant.name, &target.url);
+}
 
-        sleep(Duration::from_secs(wait)).await;
+/// Pushes the native histograms of the phase durations of a tenant.
+async fn push_phase_histograms(
+    tenant: &Tenant,
+    histograms: &PhaseHistograms,
+    context: &ProbeContext,
+) {
+    let metadata = prefixed_probe_metadata(&tenant.org_config.metric_prefix);
+    let (mut series, created_timestamps) =
+        histograms.series(Utc::now().timestamp_millis(), &tenant.org_config);
+    if context.staleness_markers {
+        series.extend(tenant.series_tracker.record(NATIVE_HISTOGRAMS_KEY, &series));
+    }
+    if let Err(e) = tenant.write(&series, &metadata, &created_timestamps).await {
+        log::error!("[{}] Failed to send phase histograms: {e}", tenant.name);
     }
 }
 
@@ -289,48 +566,168 @@ fn to_fixed_width(input: &str, width: usize) -> String {
     format!("{:<width$}", truncated, width = width)
 }
 
-/// Handles probing a target URL and sending the results to Mimir.
+/// The timeout of the HTTP request of a probe when the target sets none.
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Probes a target once using the resolver of the context, with a new trace context.
+/// The HTTP client and TLS connector are built for this probe only, see `probe_target_with`.
+/// # Arguments
+///     * `target` - The target to probe.
+///     * `context` - The shared resolver and TLS connector.
+/// # Returns
+///     The result of the probe, and the error if the probe failed.
+pub async fn probe_target(
+    target: &TargetConfig,
+    context: &ProbeContext,
+) -> (ProbeResult, Option<String>) {
+    let clients = ProbeClients::new(target, context);
+    probe_target_with(target, clients.as_ref().map_err(String::as_str), context).await
+}
+
+/// Probes a target using the resolver of the context, with a new trace context.
+/// The request carries the headers of the target and is verified using its TLS settings.
+/// When the target sets a timeout, the whole probe fails once it takes longer.
 /// # Arguments
-///     * `tenant` - The tenant name for logging and metrics.
-///     * `org_id` - The organisation ID for Mimir metrics.
+///     * `target` - The target to probe.
+///     * `clients` - The HTTP client and TLS connector of the target, or the error building them.
+///     * `context` - The shared resolver.
+/// # Returns
+///     The result of the probe, and the error if the probe failed.
+///     The result of a failed probe has zeroed metrics.
+async fn probe_target_with(
+    target: &TargetConfig,
+    clients: Result<&ProbeClients, &str>,
+    context: &ProbeContext,
+) -> (ProbeResult, Option<String>) {
+    let url = target.url.as_str();
+    let started_at_ms = Utc::now().timestamp_millis();
+    let trace_context = TraceContext::new();
+    let result = match clients {
+        Ok(clients) => {
+            let probe = probe_url(
+                clients.client.clone(),
+                &clients.tls_connector,
+                &context.resolver,
+                url,
+                &target.headers,
+                &trace_context,
+            );
+            match target.timeout_seconds {
+                Some(seconds) => tokio::time::timeout(Duration::from_secs(seconds), probe)
+                    .await
+                    .unwrap_or_else(|_| Err(format!("Probe timed out after {seconds}s"))),
+                None => probe.await,
+            }
+        }
+        Err(e) => Err(e.to_string()),
+    };
+
+    match result {
+        Ok(probe) => (probe, None),
+        Err(e) => (
+            ProbeResult::failed(url, started_at_ms, trace_context),
+            Some(e),
+        ),
+    }
+}
+
+/// The HTTP client and TLS connector a target is probed with.
+#[derive(Clone)]
+pub struct ProbeClients {
+    client: Client,
+    tls_connector: TokioTlsConnector,
+}
+
+impl ProbeClients {
+    /// Builds the HTTP client and TLS connector for probing a target.
+    /// The shared TLS connector of the context is used unless the target has TLS settings of its own.
+    /// # Errors
+    ///     Returns an error if the CA file of the target cannot be read or parsed.
+    pub fn new(target: &TargetConfig, context: &ProbeContext) -> Result<Self, String> {
+        let tls = target.tls();
+        let timeout = target
+            .timeout_seconds
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_REQUEST_TIMEOUT);
+        let mut builder = Client::builder()
+            .timeout(timeout)
+            .danger_accept_invalid_certs(tls.insecure_skip_verify)
+            .user_agent("reqwest-h2-h3-probe/1.0");
+
+        let build_client = |builder: reqwest::ClientBuilder| {
+            builder
+                .build()
+                .map_err(|e| format!("Failed to create HTTP client: {e}"))
+        };
+
+        if tls == TlsConfig::default() {
+            return Ok(ProbeClients {
+                client: build_client(builder)?,
+                tls_connector: context.tls_connector.clone(),
+            });
+        }
+
+        let mut connector = native_tls::TlsConnector::builder();
+        connector.danger_accept_invalid_certs(tls.insecure_skip_verify);
+        if let Some(ca_file) = &tls.ca_file {
+            let pem = std::fs::read(ca_file)
+                .map_err(|e| format!("Failed to read CA file {}: {e}", ca_file.display()))?;
+            let certificate = native_tls::Certificate::from_pem(&pem)
+                .map_err(|e| format!("Failed to parse CA file {}: {e}", ca_file.display()))?;
+            let root = reqwest::Certificate::from_pem(&pem)
+                .map_err(|e| format!("Failed to parse CA file {}: {e}", ca_file.display()))?;
+            connector.add_root_certificate(certificate);
+            builder = builder.add_root_certificate(root);
+        }
+        let connector = connector
+            .build()
+            .map_err(|e| format!("Failed to build TLS connector: {e}"))?;
+        Ok(ProbeClients {
+            client: build_client(builder)?,
+            tls_connector: TokioTlsConnector::from(connector),
+        })
+    }
+}
+
+/// Handles probing a target URL and writing the results to the sinks of the tenant.
+/// # Arguments
+///     * `tenant` - The tenant, holding its name, organisation ID, series tracker and sinks.
 ///     * `target` - The target configuration containing the URL and accepted status codes.
-///     * `client` - The HTTP client used for making requests.
-///     * `tls_connector` - The TLS connector for establishing secure connections.
-///     * `resolver` - The DNS resolver for resolving hostnames.
-///     * `mimir_target` - The Mimir endpoint to send metrics to.
-///     * `max_width` - The maximum width for tenant name formatting in logs.
+///     * `clients` - The HTTP client and TLS connector of the target, or the error building them.
+///     * `context` - The shared resolver, Mimir and OTLP endpoints and log formatting settings.
+///     * `metadata` - The metric metadata to send along with the metrics, may be empty.
+///     * `phase_histograms` - The native histograms to record the phase durations in, if enabled.
 async fn handle_target_probe(
-    tenant: String,
-    org_id: &str,
+    tenant: &Tenant,
     target: &TargetConfig,
-    tls_connector: &TokioTlsConnector,
-    resolver: &TokioAsyncResolver,
-    mimir_target: &str,
-    max_width: usize,
+    clients: Result<&ProbeClients, &str>,
+    context: &ProbeContext,
+    metadata: &[MetricMetadata],
+    phase_histograms: Option<&PhaseHistograms>,
 ) {
-    let client = Client::builder()
-        .timeout(Duration::from_secs(5))
-        .danger_accept_invalid_certs(true)
-        .user_agent("reqwest-h2-h3-probe/1.0")
-        .build()
-        .expect("Failed to create client");
-
     let url = &target.url;
-    let result = probe_url(client.clone(), tls_connector, resolver, url).await;
+    let org_id = tenant.org_config.organisation_id.as_str();
+    let (probe, error) = probe_target_with(target, clients, context).await;
 
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs_f64();
-    let padded_tenant = to_fixed_width(&tenant, max_width);
+    let padded_tenant = to_fixed_width(&tenant.name, context.max_org_width);
 
-    match result {
-        Ok(probe) => {
+    match error {
+        None => {
             let accepted = probe
                 .http_status
-                .map(|code| target.accepted_status_codes.contains(&code))
+                .map(|code| target.accepted_status_codes().contains(&code))
                 .unwrap_or(false);
 
+            let outcome = match accepted {
+                true => ProbeOutcome::Success,
+                false => ProbeOutcome::Failure,
+            };
+            SELF_METRICS.record_probe(&tenant.name, outcome);
+
             if accepted {
                 log::debug!(
                     "[{padded_tenant}] ✅ URL: {}, Status: {:?}, Elapsed: {:.2}ms, Cert: {}",
@@ -346,34 +743,51 @@ async fn handle_target_probe(
                 log::error!(
                     "[{padded_tenant}] ❌ Unexpected status for {url}: {:?} (accepted: {:?})",
                     probe.http_status,
-                    target.accepted_status_codes
+                    target.accepted_status_codes()
                 );
             }
 
-            let metrics = create_probe_metrics(&probe, accepted);
-            if let Err(e) = send_to_mimir(mimir_target, Some(org_id), metrics).await {
+            let timestamp_ms = probe.sample_timestamp_ms(context.sample_timestamp);
+            let mut metrics = create_probe_metrics(&probe, accepted, timestamp_ms);
+            label_series(&mut metrics, target, &tenant.org_config);
+            if let Some(histograms) = phase_histograms {
+                histograms.observe(&probe);
+                if !histograms.keep_phase_gauges() {
+                    remove_phase_duration_gauges(&mut metrics);
+                }
+            }
+            if context.staleness_markers {
+                metrics.extend(tenant.series_tracker.record(url, &metrics));
+            }
+            if let Err(e) = tenant.write(&metrics, metadata, &[]).await {
                 log::error!("[{padded_tenant}] Failed to send metrics for {url}: {e}");
             }
+            if let Some(endpoint) = &context.otlp_traces_endpoint {
+                let trace = create_probe_trace(&tenant.name, org_id, &probe, accepted, None);
+                if let Err(e) = export(endpoint, &TRACES, &trace).await {
+                    log::error!("[{padded_tenant}] Failed to export trace for {url}: {e}");
+                }
+            }
         }
-        Err(e) => {
+        Some(e) => {
             // in case we cannot probe the url, send a failed probe with zeroed metrics
             log::error!("[{padded_tenant}] ❌ Probe error for {url}: {e}");
-            let probe = ProbeResult {
-                url: url.to_string(),
-                dns_time: None,
-                connect_time: None,
-                tls_time: None,
-                processing_time: None,
-                cert_validity_seconds: None,
-                http_status: None,
-                http_version: None,
-                transfer_time: None,
-                total_probe_time: 0.0,
-            };
-            let metrics = create_probe_metrics(&probe, false);
-            if let Err(e) = send_to_mimir(mimir_target, Some(org_id), metrics).await {
+            SELF_METRICS.record_probe(&tenant.name, ProbeOutcome::Failure);
+            let timestamp_ms = probe.sample_timestamp_ms(context.sample_timestamp);
+            let mut metrics = create_probe_metrics(&probe, false, timestamp_ms);
+            label_series(&mut metrics, target, &tenant.org_config);
+            if context.staleness_markers {
+                metrics.extend(tenant.series_tracker.record(url, &metrics));
+            }
+            if let Err(e) = tenant.write(&metrics, metadata, &[]).await {
                 log::error!("[{padded_tenant}] Failed to send error metrics for {url}: {e}");
             }
+            if let Some(endpoint) = &context.otlp_traces_endpoint {
+                let trace = create_probe_trace(&tenant.name, org_id, &probe, false, Some(&e));
+                if let Err(e) = export(endpoint, &TRACES, &trace).await {
+                    log::error!("[{padded_tenant}] Failed to export trace for {url}: {e}");
+                }
+            }
         }
     }
 }
@@ -1,8 +1,18 @@
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use super::trace::TraceContext;
+
 /// Struct to hold the results of an HTTP probe.
 pub struct ProbeResult {
     /// The URL that was probed.
     pub url: String,
 
+    /// The unix timestamp in milliseconds at which the probe started.
+    /// All series of a probe are stamped with a timestamp derived from this, see `SampleTimestamp`.
+    pub started_at_ms: i64,
+
     /// The time taken for DNS resolution, in seconds.
     /// This is the time from when the request was sent until the DNS lookup was completed.
     /// DNS resolution is performed by the configured DNS resolvers.
@@ -39,4 +49,94 @@ pub struct ProbeResult {
     /// The total time taken for the probe, in seconds.
     /// This is the sum of all phases: DNS resolution, connection, TLS handshake, processing, and transfer.
     pub total_probe_time: f64,
+
+    /// The trace context of the probe, injected into the probe request as a `traceparent` header.
+    pub trace_context: TraceContext,
+}
+
+impl ProbeResult {
+    /// Creates the result of a probe which failed, with zeroed metrics.
+    pub fn failed(url: &str, started_at_ms: i64, trace_context: TraceContext) -> Self {
+        ProbeResult {
+            url: url.to_string(),
+            started_at_ms,
+            dns_time: None,
+            connect_time: None,
+            tls_time: None,
+            http_status: None,
+            http_version: None,
+            cert_validity_seconds: None,
+            processing_time: None,
+            transfer_time: None,
+            total_probe_time: 0.0,
+            trace_context,
+        }
+    }
+
+    /// Returns the timestamp, in unix milliseconds, to stamp all samples of this probe with.
+    pub fn sample_timestamp_ms(&self, sample_timestamp: SampleTimestamp) -> i64 {
+        match sample_timestamp {
+            SampleTimestamp::ProbeStart => self.started_at_ms,
+            SampleTimestamp::ProbeEnd => {
+                self.started_at_ms + (self.total_probe_time * 1000.0).round() as i64
+            }
+        }
+    }
+}
+
+/// The moment of a probe used as the timestamp of its samples.
+/// All series of one probe share the same timestamp, so the phase metrics line up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleTimestamp {
+    /// The time the probe started.
+    #[default]
+    ProbeStart,
+    /// The time the probe completed.
+    ProbeEnd,
+}
+
+impl FromStr for SampleTimestamp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "probe_start" | "start" => Ok(SampleTimestamp::ProbeStart),
+            "probe_end" | "end" => Ok(SampleTimestamp::ProbeEnd),
+            other => Err(format!(
+                "Unsupported sample timestamp '{other}', expected 'probe_start' or 'probe_end'"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_timestamp() {
+        let probe = ProbeResult {
+            url: "https://example.com".to_string(),
+            started_at_ms: 1_000,
+            dns_time: None,
+            connect_time: None,
+            tls_time: None,
+            http_status: None,
+            http_version: None,
+            cert_validity_seconds: None,
+            processing_time: None,
+            transfer_time: None,
+            total_probe_time: 0.25,
+            trace_context: TraceContext::new(),
+        };
+
+        assert_eq!(
+            probe.sample_timestamp_ms(SampleTimestamp::ProbeStart),
+            1_000
+        );
+        assert_eq!(probe.sample_timestamp_ms(SampleTimestamp::ProbeEnd), 1_250);
+        assert_eq!("probe_end".parse(), Ok(SampleTimestamp::ProbeEnd));
+        assert!("push".parse::<SampleTimestamp>().is_err());
+    }
 }
@@ -0,0 +1,193 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A cron expression of a target, such as `*/30 * 8-18 * * Mon-Fri`, evaluated in UTC.
+/// Both the five field form of crontab and the six field form with seconds are accepted. In the five
+/// field form, days of the week are numbered like crontab does, from `0` for Sunday to `6` for
+/// Saturday, with `7` being Sunday as well. The six field form numbers them like the `cron` crate,
+/// from `1` for Sunday to `7` for Saturday.
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    /// The expression as configured.
+    source: String,
+    schedule: cron::Schedule,
+}
+
+impl CronSchedule {
+    /// Parses a cron expression. A five field expression runs at the start of the minute.
+    /// # Errors
+    ///     Returns an error if the expression is not a valid cron expression.
+    pub fn new(source: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = source.split_whitespace().collect();
+        let expression = match fields.as_slice() {
+            [minute, hour, day, month, day_of_week] => format!(
+                "0 {minute} {hour} {day} {month} {}",
+                crontab_day_of_week(day_of_week)
+            ),
+            _ => source.to_string(),
+        };
+        let schedule = cron::Schedule::from_str(&expression).map_err(|e| {
+            // the error of the cron crate repeats the expression and points at it on its own lines
+            let e = e.to_string();
+            let reason = e.lines().last().unwrap_or_default();
+            format!("'{source}' is not a valid cron expression: {reason}")
+        })?;
+        Ok(CronSchedule {
+            source: source.to_string(),
+            schedule,
+        })
+    }
+
+    /// Returns the first moment of the schedule after `after`, if any.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.schedule.after(&after).next()
+    }
+}
+
+/// Converts the numeric days of the week of a crontab field, counting from `0` or `7` for Sunday, to
+/// the `cron` crate, counting from `1` for Sunday. Numeric ranges and steps are expanded into a list
+/// of days, e.g. `5-7` into `6,7,1`. Names, such as `Mon-Fri`, and invalid days are left as they are.
+fn crontab_day_of_week(field: &str) -> String {
+    field
+        .split(',')
+        .map(|item| {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, step.parse::<usize>().ok()),
+                None => (item, Some(1)),
+            };
+            let (first, last) = match range.split_once('-') {
+                Some((first, last)) => (first, last),
+                // a single day with a step runs until the end of the week
+                None if step != Some(1) => (range, "6"),
+                None => (range, range),
+            };
+            match (first.parse::<u32>(), last.parse::<u32>(), step) {
+                (Ok(first), Ok(last), Some(step)) if first <= last && last <= 7 && step > 0 => {
+                    let mut days: Vec<u32> = (first..=last)
+                        .step_by(step)
+                        .map(|day| day % 7 + 1)
+                        .collect();
+                    days.dedup();
+                    days.iter()
+                        .map(u32::to_string)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                }
+                _ => item.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl PartialEq for CronSchedule {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl<'de> Deserialize<'de> for CronSchedule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        CronSchedule::new(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for CronSchedule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.source.serialize(serializer)
+    }
+}
+
+/// When a target is probed: every interval, or at the moments of a cron schedule.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProbeSchedule {
+    Interval(Duration),
+    Cron(Box<CronSchedule>),
+}
+
+impl ProbeSchedule {
+    /// Returns how long to wait for the next probe.
+    /// # Arguments
+    ///     * `scheduled_at` - When the previous probe was scheduled, `None` for the first probe.
+    ///     * `now` - The current time.
+    /// # Returns
+    ///     The time to wait, zero when the probe is due already, or `None` when the cron schedule has
+    ///     no moments left.
+    pub fn wait(
+        &self,
+        scheduled_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Option<Duration> {
+        let next = match self {
+            // the first probe of an interval runs right away
+            ProbeSchedule::Interval(interval) => match scheduled_at {
+                None => now,
+                Some(scheduled_at) => scheduled_at + *interval,
+            },
+            ProbeSchedule::Cron(cron) => cron.next_after(scheduled_at.unwrap_or(now).max(now))?,
+        };
+        Some((next - now).to_std().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_probe_schedule() {
+        let interval = ProbeSchedule::Interval(Duration::from_secs(30));
+        let now = at("2025-06-02T08:00:10Z");
+        assert_eq!(interval.wait(None, now), Some(Duration::ZERO));
+        assert_eq!(
+            interval.wait(Some(at("2025-06-02T08:00:00Z")), now),
+            Some(Duration::from_secs(20))
+        );
+        // a probe which overran its interval is followed by the next one right away
+        assert_eq!(
+            interval.wait(Some(at("2025-06-02T07:59:00Z")), now),
+            Some(Duration::ZERO)
+        );
+
+        // every 5 minutes during business hours, on weekdays
+        let cron =
+            ProbeSchedule::Cron(Box::new(CronSchedule::new("*/5 8-17 * * Mon-Fri").unwrap()));
+        assert_eq!(cron.wait(None, now), Some(Duration::from_secs(290)));
+        let friday_evening = at("2025-06-06T17:55:00Z");
+        assert_eq!(
+            cron.wait(Some(friday_evening), friday_evening),
+            Some(Duration::from_secs((2 * 24 + 14) * 3600 + 5 * 60))
+        );
+
+        // numeric days of the week count from Sunday as 0, like crontab
+        let numeric = ProbeSchedule::Cron(Box::new(CronSchedule::new("*/5 8-17 * * 1-5").unwrap()));
+        assert_eq!(numeric.wait(None, now), Some(Duration::from_secs(290)));
+        assert_eq!(
+            numeric.wait(Some(friday_evening), friday_evening),
+            Some(Duration::from_secs((2 * 24 + 14) * 3600 + 5 * 60))
+        );
+        assert_eq!(crontab_day_of_week("1-5"), "2,3,4,5,6");
+        assert_eq!(crontab_day_of_week("0,7"), "1,1");
+        assert_eq!(crontab_day_of_week("5-7"), "6,7,1");
+        assert_eq!(crontab_day_of_week("*/2"), "*/2");
+        assert_eq!(crontab_day_of_week("1/2"), "2,4,6");
+        assert_eq!(crontab_day_of_week("Mon-Fri"), "Mon-Fri");
+        let sunday = CronSchedule::new("0 12 * * 0").unwrap();
+        assert_eq!(sunday.next_after(now), Some(at("2025-06-08T12:00:00Z")));
+        assert_eq!(
+            CronSchedule::new("0 12 * * 7").unwrap().next_after(now),
+            sunday.next_after(now)
+        );
+
+        assert!(CronSchedule::new("every minute").is_err());
+        assert!(CronSchedule::new("0 */10 * * * *").is_ok());
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, timeout_at};
+
+use crate::config::probe_config::{Config, OrganisationConfig};
+use crate::exposition::LatestSeries;
+use crate::mimir::client::prometheus::prompb::TimeSeries;
+use crate::mimir::staleness::SeriesTracker;
+use crate::self_monitoring::SELF_METRICS;
+use crate::self_monitoring::health::HEALTH;
+use crate::sink::MetricsSinks;
+use crate::sink::exposition::ExpositionSink;
+
+use super::probe::{LoopControl, NATIVE_HISTOGRAMS_KEY, ProbeContext, Tenant, run_probe_loop};
+
+/// The probe loop of a tenant, together with the tenant it probes for.
+struct RunningTenant {
+    tenant: Arc<Tenant>,
+    handle: JoinHandle<()>,
+    control: watch::Sender<LoopControl>,
+}
+
+impl RunningTenant {
+    /// Cancels the probe loop and waits until it and the probes in flight have stopped.
+    async fn cancel(&mut self) {
+        self.control.send_replace(LoopControl::Cancel);
+        if let Err(join_err) = (&mut self.handle).await {
+            SELF_METRICS.record_task_panic(&self.tenant.name);
+            log::error!("Task panicked: {:?}", join_err);
+        }
+    }
+}
+
+/// Runs the probe loop of every tenant, and applies a changed probe configuration to the running
+/// loops, only touching the tenants whose configuration changed.
+pub struct Supervisor {
+    context: ProbeContext,
+    latest: Option<Arc<LatestSeries>>,
+    tenants: HashMap<String, RunningTenant>,
+}
+
+impl Supervisor {
+    /// Creates a supervisor without any running tenant.
+    /// # Arguments
+    ///     * `context` - The resources shared by every probe loop.
+    ///     * `latest` - The latest series of every tenant, exposed on `/metrics`, if the HTTP server runs.
+    pub fn new(context: ProbeContext, latest: Option<Arc<LatestSeries>>) -> Self {
+        Supervisor {
+            context,
+            latest,
+            tenants: HashMap::new(),
+        }
+    }
+
+    /// Applies a probe configuration to the running tenants:
+    ///     * New tenants are started.
+    ///     * Removed tenants are stopped, and their series marked stale.
+    ///     * Changed tenants are restarted. When the organisation ID and sinks are unchanged, the
+    ///       series of the removed targets are marked stale and the other series carry over.
+    ///       Otherwise the tenant is stopped and started afresh.
+    pub async fn apply(&mut self, mut config: Config) {
+        let removed: Vec<String> = self
+            .tenants
+            .keys()
+            .filter(|name| !config.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in removed {
+            log::info!("Stopping tenant {name}, it was removed from the configuration");
+            self.stop(&name).await;
+        }
+
+        let mut names: Vec<String> = config.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            let org_config = config.remove(&name).unwrap();
+            let Some(running) = self.tenants.get(&name) else {
+                log::info!("Starting tenant {name}");
+                self.start(name, org_config, None);
+                continue;
+            };
+
+            let previous = &running.tenant.org_config;
+            if *previous == org_config {
+                continue;
+            }
+            if previous.organisation_id != org_config.organisation_id
+                || previous.sinks != org_config.sinks
+            {
+                log::info!("Restarting tenant {name} with a new organisation ID or sinks");
+                self.stop(&name).await;
+                self.start(name, org_config, None);
+                continue;
+            }
+
+            log::info!("Restarting tenant {name} with its changed configuration");
+            let mut running = self.tenants.remove(&name).unwrap();
+            running.cancel().await;
+            let tenant = running.tenant;
+            let stale_series = match self.context.staleness_markers {
+                true => removed_series(&tenant, &org_config),
+                false => vec![],
+            };
+            self.write_stale_series(&tenant, &stale_series).await;
+            self.start(
+                name,
+                org_config,
+                Some((tenant.series_tracker.clone(), tenant.sinks.clone())),
+            );
+        }
+    }
+
+    /// Shuts down every tenant gracefully: no new probe cycle is started, the probes in flight may
+    /// complete until the timeout, after which they are cancelled. Then the series of every tenant
+    /// are marked stale and their sinks flushed.
+    /// All tenants drain at the same time and are only retired once every tenant stopped, so every
+    /// tenant gets the whole timeout for its probes, however long the others take to retire.
+    /// # Arguments
+    ///     * `timeout` - How long to wait for the probes in flight of every tenant.
+    /// # Returns
+    ///     `true` when all probes completed and every write and flush succeeded.
+    pub async fn shutdown(mut self, timeout: Duration) -> bool {
+        for running in self.tenants.values() {
+            running.control.send_replace(LoopControl::Drain);
+        }
+        let deadline = Instant::now() + timeout;
+        let mut clean = true;
+
+        let mut stopped: Vec<RunningTenant> =
+            self.tenants.drain().map(|(_, running)| running).collect();
+        stopped.sort_by(|a, b| a.tenant.name.cmp(&b.tenant.name));
+        for running in &mut stopped {
+            if timeout_at(deadline, &mut running.handle).await.is_err() {
+                log::warn!(
+                    "Cancelling the probes of {} which did not complete in {timeout:?}",
+                    running.tenant.name
+                );
+                running.cancel().await;
+                clean = false;
+            }
+        }
+
+        for running in &stopped {
+            clean &= self.retire(&running.tenant).await;
+        }
+        clean
+    }
+
+    /// Starts the probe loop of a tenant.
+    /// # Arguments
+    ///     * `name` - The tenant name.
+    ///     * `org_config` - The configuration of the organisation.
+    ///     * `state` - The series tracker and sinks of the previous loop of the tenant, to carry over.
+    fn start(
+        &mut self,
+        name: String,
+        org_config: OrganisationConfig,
+        state: Option<(Arc<SeriesTracker>, Arc<MetricsSinks>)>,
+    ) {
+        let (series_tracker, sinks) = state.unwrap_or_else(|| {
+            let mut sinks = MetricsSinks::from_config(
+                &org_config.sinks,
+                &self.context.mimir_endpoint,
+                &self.context.external_labels,
+            );
+            if let Some(latest) = &self.latest {
+                sinks = sinks.with_sink(Box::new(ExpositionSink::new(latest.clone())));
+            }
+            (Arc::new(SeriesTracker::default()), Arc::new(sinks))
+        });
+        let tenant = Arc::new(Tenant::new(
+            name.clone(),
+            org_config,
+            series_tracker,
+            sinks,
+            &self.context,
+        ));
+        let (control, receiver) = watch::channel(LoopControl::Run);
+        let handle = tokio::spawn(run_probe_loop(
+            tenant.clone(),
+            self.context.clone(),
+            receiver,
+        ));
+        self.tenants.insert(
+            name,
+            RunningTenant {
+                tenant,
+                handle,
+                control,
+            },
+        );
+    }
+
+    /// Stops the probe loop of a tenant right away, cancelling its probes in flight, see `retire`.
+    async fn stop(&mut self, name: &str) {
+        if let Some(mut running) = self.tenants.remove(name) {
+            running.cancel().await;
+            self.retire(&running.tenant).await;
+        }
+    }
+
+    /// Marks the series of a stopped tenant stale, flushes its sinks and forgets the tenant.
+    /// # Returns
+    ///     `true` when the staleness markers were written and the sinks flushed.
+    async fn retire(&self, tenant: &Tenant) -> bool {
+        let stale_series = match self.context.staleness_markers {
+            true => tenant.series_tracker.drain(),
+            false => vec![],
+        };
+        let mut clean = self.write_stale_series(tenant, &stale_series).await;
+        if let Err(e) = tenant.sinks.flush().await {
+            log::error!(
+                "Failed to flush the sinks of {}: {e}",
+                tenant.org_config.organisation_id
+            );
+            clean = false;
+        }
+        if let Some(latest) = &self.latest {
+            latest.remove(&tenant.name);
+        }
+        HEALTH.remove_tenant(&tenant.name);
+        SELF_METRICS.remove_tenant(&tenant.name);
+        clean
+    }
+
+    /// Writes staleness markers to the sinks of a tenant.
+    /// # Returns
+    ///     `true` when there was nothing to write, or the markers were written.
+    async fn write_stale_series(&self, tenant: &Tenant, stale_series: &[TimeSeries]) -> bool {
+        if stale_series.is_empty() {
+            return true;
+        }
+        let org_id = &tenant.org_config.organisation_id;
+        log::info!("Marking {} series of {org_id} as stale", stale_series.len());
+        match tenant.write(stale_series, &[], &[]).await {
+            Ok(()) => true,
+            Err(e) => {
+                log::error!("Failed to send staleness markers for {org_id}: {e}");
+                false
+            }
+        }
+    }
+}
+
+/// Forgets the targets of a tenant which are no longer in its new configuration, and the native
+/// histograms when they were disabled, returning staleness markers for their series.
+fn removed_series(tenant: &Tenant, org_config: &OrganisationConfig) -> Vec<TimeSeries> {
+    let mut stale_series = Vec::new();
+    for target in &tenant.org_config.targets {
+        if !org_config.targets.iter().any(|t| t.url == target.url) {
+            stale_series.extend(tenant.series_tracker.remove_target(&target.url));
+        }
+    }
+    if org_config.native_histograms.is_none() {
+        stale_series.extend(tenant.series_tracker.remove_target(NATIVE_HISTOGRAMS_KEY));
+    }
+    stale_series
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::app_config::{setup_resolver, setup_tls_connector};
+    use crate::config::probe_config::TargetConfig;
+    use crate::mimir::client::{MimirEndpoint, create_time_series};
+
+    fn org_config(urls: &[&str]) -> OrganisationConfig {
+        OrganisationConfig {
+            organisation_id: "org-1".to_string(),
+            polling_interval_seconds: 10,
+            targets: urls
+                .iter()
+                .map(|url| TargetConfig {
+                    url: url.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            native_histograms: None,
+            sinks: vec![],
+            job_name: "oxybox".to_string(),
+            instance_label: Default::default(),
+            metric_prefix: String::new(),
+            labels: Default::default(),
+            defaults: Default::default(),
+            relabel_configs: vec![],
+            metric_relabel_configs: vec![],
+        }
+    }
+
+    #[test]
+    fn test_removed_series() {
+        let endpoint = MimirEndpoint {
+            url: "http://localhost:9009".to_string(),
+            remote_write_version: Default::default(),
+            metadata_send_interval: Default::default(),
+        };
+        let tenant = Tenant {
+            name: "acme".to_string(),
+            org_config: org_config(&["https://a.example.com", "https://b.example.com"]),
+            series_tracker: Arc::new(SeriesTracker::default()),
+            sinks: Arc::new(MetricsSinks::from_config(
+                &[],
+                &endpoint,
+                &Default::default(),
+            )),
+            clients: vec![],
+        };
+        for url in ["https://a.example.com", "https://b.example.com"] {
+            let series = create_time_series("probe_success", &[("instance", url)], 1.0, None);
+            tenant.series_tracker.record(url, &[series]);
+        }
+
+        let stale_series = removed_series(&tenant, &org_config(&["https://a.example.com"]));
+        assert_eq!(stale_series.len(), 1);
+        assert!(
+            stale_series[0]
+                .labels
+                .iter()
+                .any(|label| label.value == "https://b.example.com")
+        );
+        assert!(
+            tenant
+                .series_tracker
+                .remove_target("https://a.example.com")
+                .len()
+                == 1
+        );
+    }
+
+    fn probe_context() -> ProbeContext {
+        ProbeContext {
+            resolver: setup_resolver(&[]).unwrap(),
+            tls_connector: setup_tls_connector().unwrap(),
+            mimir_endpoint: MimirEndpoint {
+                url: "http://localhost:9009".to_string(),
+                remote_write_version: Default::default(),
+                metadata_send_interval: Default::default(),
+            },
+            external_labels: Default::default(),
+            max_org_width: 10,
+            staleness_markers: false,
+            sample_timestamp: Default::default(),
+            otlp_traces_endpoint: None,
+        }
+    }
+
+    /// Starts a supervisor probing a target which accepts connections but never answers, and waits
+    /// until the probe of the tenant is in flight. The connection is returned to keep it open.
+    async fn supervise_hanging_target(tenant: &str) -> (Supervisor, tokio::net::TcpStream) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let mut org_config = org_config(&[&url]);
+        org_config.targets[0].timeout_seconds = Some(1);
+
+        let mut supervisor = Supervisor::new(probe_context(), None);
+        supervisor
+            .apply(Config::from([(tenant.to_string(), org_config)]))
+            .await;
+        let (connection, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        (supervisor, connection)
+    }
+
+    /// Whether a probe of the tenant was counted, which a probe surviving its loop would be once it
+    /// times out.
+    async fn probe_counted(tenant: &str) -> bool {
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        SELF_METRICS.series(0).iter().any(|series| {
+            series
+                .labels
+                .iter()
+                .any(|label| label.name == "tenant" && label.value == tenant)
+        })
+    }
+
+    #[tokio::test]
+    async fn test_stop_cancels_probes_in_flight() {
+        let (mut supervisor, _connection) = supervise_hanging_target("cancelled").await;
+        supervisor.apply(Config::new()).await;
+        assert!(!probe_counted("cancelled").await);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_probes_after_timeout() {
+        let (supervisor, _connection) = supervise_hanging_target("shut-down").await;
+        assert!(!supervisor.shutdown(Duration::from_millis(100)).await);
+        assert!(!probe_counted("shut-down").await);
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt::Write;
+
+/// The W3C trace context of a single probe request.
+/// The context is injected into the probe request as a `traceparent` header, and its trace ID is
+/// attached to the probe metrics as an exemplar, linking a slow probe to the backend trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    /// The 16 byte trace ID shared by all spans of the trace.
+    pub trace_id: [u8; 16],
+
+    /// The 8 byte ID of the span of the probe, the parent of the spans created by the backend.
+    pub span_id: [u8; 8],
+}
+
+impl TraceContext {
+    /// Creates a new, sampled trace context with a random trace and span ID.
+    pub fn new() -> Self {
+        TraceContext {
+            trace_id: random_non_zero(),
+            span_id: random_non_zero(),
+        }
+    }
+
+    /// Creates the context of a child span within the same trace, with a new random span ID.
+    pub fn child(&self) -> Self {
+        TraceContext {
+            trace_id: self.trace_id,
+            span_id: random_non_zero(),
+        }
+    }
+
+    /// Returns the trace ID as a lowercase hex string.
+    pub fn trace_id_hex(&self) -> String {
+        to_hex(&self.trace_id)
+    }
+
+    /// Returns the span ID as a lowercase hex string.
+    pub fn span_id_hex(&self) -> String {
+        to_hex(&self.span_id)
+    }
+
+    /// Returns the value of the `traceparent` header, as defined by the W3C Trace Context specification.
+    /// The sampled flag is always set, so the backend records the trace of every probe.
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-01", self.trace_id_hex(), self.span_id_hex())
+    }
+}
+
+impl Default for TraceContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns random bytes which are not all zero, as all-zero IDs are invalid in W3C Trace Context.
+fn random_non_zero<const N: usize>() -> [u8; N] {
+    loop {
+        let bytes: [u8; N] = rand::random();
+        if bytes.iter().any(|b| *b != 0) {
+            return bytes;
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traceparent() {
+        let context = TraceContext {
+            trace_id: [
+                0x4b, 0xf9, 0x2f, 0x35, 0x77, 0xb3, 0x4d, 0xa6, 0xa3, 0xce, 0x92, 0x9d, 0x0e, 0x0e,
+                0x47, 0x36,
+            ],
+            span_id: [0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7],
+        };
+        assert_eq!(
+            context.traceparent(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+    }
+
+    #[test]
+    fn test_new_trace_context_is_valid() {
+        let context = TraceContext::new();
+        assert_eq!(context.trace_id_hex().len(), 32);
+        assert_eq!(context.span_id_hex().len(), 16);
+        assert_ne!(context, TraceContext::new());
+
+        let child = context.child();
+        assert_eq!(child.trace_id, context.trace_id);
+        assert_ne!(child.span_id, context.span_id);
+    }
+}
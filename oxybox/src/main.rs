@@ -1,39 +1,202 @@
-use config::app_config::{load_config, setup_resolver, setup_tls_connector};
+use clap::Parser;
+use cli::{Cli, Command};
+use config::app_config::{load_config, load_probe_config, setup_resolver, setup_tls_connector};
+use config::interpolate::SECRETS;
+use config::reload::ConfigWatcher;
+use config::settings::Settings;
 use dotenvy::dotenv;
+use exposition::LatestSeries;
+use self_monitoring::SELF_METRICS;
+use self_monitoring::health::HEALTH;
+use server::ServerState;
+use std::io::Write;
+use std::process::ExitCode;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::time::sleep;
 pub mod http_probe;
-use http_probe::probe::run_probe_loop;
+use http_probe::probe::ProbeContext;
+use http_probe::supervisor::Supervisor;
+pub mod cli;
 pub mod config;
+pub mod exposition;
 pub mod mimir;
+pub mod otlp;
+pub mod self_monitoring;
+pub mod server;
+pub mod sink;
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     dotenv().ok();
-    env_logger::init();
-    let app_config = load_config();
-    let resolver = setup_resolver(&app_config.dns_hosts).expect("Failed to init resolver");
+    env_logger::Builder::from_default_env()
+        .format(|buf, record| {
+            let style = buf.default_level_style(record.level());
+            writeln!(
+                buf,
+                "[{} {style}{:<5}{style:#} {}] {}",
+                buf.timestamp(),
+                record.level(),
+                record.target(),
+                SECRETS.redact(&record.args().to_string())
+            )
+        })
+        .init();
+
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Run(cli.settings)) {
+        Command::Run(settings) => run(settings).await,
+        Command::Validate { file } => cli::validate(&file),
+        Command::Check {
+            url,
+            tenant,
+            accepted_status_codes,
+            timeout_seconds,
+            settings,
+        } => {
+            let timeout = timeout_seconds.map(Duration::from_secs);
+            cli::check(
+                &url,
+                tenant.as_deref(),
+                &accepted_status_codes,
+                timeout,
+                settings,
+            )
+            .await
+        }
+        Command::PrintConfig(settings) => cli::print_config(settings),
+    }
+}
+
+/// Runs Oxybox: probes the targets of every organisation until it is shut down, serving the probe
+/// results and health endpoints, and reloading the probe configuration when it changes.
+async fn run(settings: Settings) -> ExitCode {
+    let app_config = match load_config(settings) {
+        Ok(app_config) => app_config,
+        Err(e) => {
+            log::error!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let resolver = match setup_resolver(&app_config.dns_hosts) {
+        Ok(resolver) => resolver,
+        Err(e) => {
+            log::error!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
     let tls_connector = setup_tls_connector().expect("Failed to build TLS connector");
 
-    log::info!("Using Mimir endpoint: {}", app_config.mimir_endpoint);
-
-    for (key, org_config) in app_config.config {
-        let resolver = resolver.clone();
-        let tls_connector = tls_connector.clone();
-        let max_org_width = app_config.max_org_width;
-        let mimir_endpoint = app_config.mimir_endpoint.clone();
-
-        tokio::spawn(run_probe_loop(
-            key,
-            org_config,
-            resolver,
-            tls_connector,
-            mimir_endpoint,
-            max_org_width,
+    log::info!(
+        "Using Mimir endpoint: {} (remote write {:?})",
+        app_config.mimir_endpoint.url,
+        app_config.mimir_endpoint.remote_write_version
+    );
+
+    let context = ProbeContext {
+        resolver,
+        tls_connector,
+        mimir_endpoint: app_config.mimir_endpoint,
+        external_labels: app_config.external_labels,
+        max_org_width: app_config.max_org_width,
+        staleness_markers: app_config.staleness_markers,
+        sample_timestamp: app_config.sample_timestamp,
+        otlp_traces_endpoint: app_config.otlp_traces_endpoint,
+    };
+
+    let latest = app_config.listen_address.map(|listen_address| {
+        let latest = Arc::new(LatestSeries::default());
+        let server_state = Arc::new(ServerState {
+            latest: latest.clone(),
+            probe_context: context.clone(),
+            readiness_max_write_age: app_config.readiness_max_write_age,
+        });
+        tokio::spawn(async move {
+            if let Err(e) = server::serve(listen_address, server_state).await {
+                log::error!("Failed to start the HTTP server on {listen_address}: {e}");
+            }
+        });
+        latest
+    });
+
+    if let Some(admin_tenant) = app_config.admin_tenant {
+        log::info!("Pushing the Oxybox metrics to tenant {admin_tenant}");
+        tokio::spawn(self_monitoring::run_push_loop(
+            context.mimir_endpoint.clone(),
+            context.external_labels.clone(),
+            admin_tenant,
+            app_config.admin_push_interval,
         ));
     }
 
+    let mut supervisor = Supervisor::new(context, latest);
+    supervisor.apply(app_config.config).await;
+    HEALTH.mark_config_loaded();
+
+    let mut watcher =
+        ConfigWatcher::new(&app_config.config_file, app_config.config_reload_interval);
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
     loop {
-        sleep(Duration::from_secs(60)).await;
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = watcher.changed() => reload(&mut supervisor, &app_config.config_file).await,
+        }
+    }
+
+    log::info!(
+        "Shutting down, waiting up to {:?} for the probes in flight",
+        app_config.shutdown_timeout
+    );
+    HEALTH.mark_shutting_down();
+    match supervisor.shutdown(app_config.shutdown_timeout).await {
+        true => {
+            log::info!("Shut down gracefully");
+            ExitCode::SUCCESS
+        }
+        false => {
+            log::error!("Shut down with cancelled probes or unsent metrics");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Reloads the probe configuration and applies it to the running tenants.
+/// When the configuration is invalid, the running configuration is kept.
+async fn reload(supervisor: &mut Supervisor, config_file: &str) {
+    match load_probe_config(config_file) {
+        Ok(config) => {
+            log::info!("Reloading the probe configuration from {config_file}");
+            supervisor.apply(config).await;
+            SELF_METRICS.record_config_reload(true);
+        }
+        Err(e) => {
+            log::error!("Keeping the running probe configuration, failed to reload: {e}");
+            SELF_METRICS.record_config_reload(false);
+        }
+    }
+}
+
+/// Waits until the process receives Ctrl+C or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
     }
 }
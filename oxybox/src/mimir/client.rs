@@ -4,39 +4,263 @@ pub mod prometheus {
     }
 }
 
+pub mod io {
+    pub mod prometheus {
+        pub mod write {
+            pub mod v2 {
+                include!("../proto_generated/io.prometheus.write.v2.rs");
+            }
+        }
+    }
+}
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
 use chrono::Utc;
-use prometheus::prompb::{Label, Sample, TimeSeries, WriteRequest};
+use io::prometheus::write::v2;
+use prometheus::prompb::{
+    Exemplar, Histogram, Label, MetricMetadata, Sample, TimeSeries, WriteRequest, histogram,
+    metric_metadata::MetricType,
+};
 use reqwest::{
-    Client,
+    Client, Response, StatusCode,
     header::{CONTENT_ENCODING, CONTENT_TYPE, HeaderMap, HeaderValue},
 };
 
+use serde::{Deserialize, Deserializer, Serialize};
 use snap::raw::Encoder;
 
+/// The Prometheus remote write protocol version used when pushing metrics to Mimir.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum RemoteWriteVersion {
+    /// Remote Write 1.0, sending a `prometheus.WriteRequest`.
+    #[default]
+    #[serde(rename = "1")]
+    V1,
+    /// Remote Write 2.0, sending an `io.prometheus.write.v2.Request` with an interned symbol table.
+    /// Falls back to 1.0 when the receiver answers with `415 Unsupported Media Type`.
+    #[serde(rename = "2")]
+    V2,
+}
+
+impl RemoteWriteVersion {
+    fn content_type(&self) -> &'static str {
+        match self {
+            RemoteWriteVersion::V1 => "application/x-protobuf",
+            RemoteWriteVersion::V2 => "application/x-protobuf;proto=io.prometheus.write.v2.Request",
+        }
+    }
+
+    fn version_header(&self) -> &'static str {
+        match self {
+            RemoteWriteVersion::V1 => "0.1.0",
+            RemoteWriteVersion::V2 => "2.0.0",
+        }
+    }
+}
+
+/// A Mimir remote write endpoint together with the protocol version configured for it.
+#[derive(Debug, Clone)]
+pub struct MimirEndpoint {
+    /// The base URL of the Mimir instance (e.g., "http://localhost:9009").
+    pub url: String,
+
+    /// The remote write protocol version used when pushing to this endpoint.
+    pub remote_write_version: RemoteWriteVersion,
+
+    /// How often metric metadata (HELP, TYPE, UNIT) is sent along with a Remote Write 1.0 push.
+    /// Remote Write 2.0 carries metadata on every series, so it is always sent.
+    pub metadata_send_interval: Duration,
+}
+
+impl FromStr for RemoteWriteVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "1" | "1.0" | "v1" => Ok(RemoteWriteVersion::V1),
+            "2" | "2.0" | "v2" => Ok(RemoteWriteVersion::V2),
+            other => Err(format!(
+                "Unsupported remote write version '{other}', expected '1' or '2'"
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RemoteWriteVersion {
+    /// Accepts the version as a number, e.g. `2`, or as a string, e.g. `"2.0"`.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Version {
+            Number(f64),
+            Text(String),
+        }
+        let version = match Version::deserialize(deserializer)? {
+            Version::Number(number) => number.to_string(),
+            Version::Text(text) => text,
+        };
+        version.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A remote write to Mimir: the series, together with what is sent along with them.
+#[derive(Debug, Clone, Default)]
+pub struct RemoteWrite {
+    /// The series to send.
+    pub metrics: Vec<TimeSeries>,
+
+    /// The metadata of the metric families to send along, may be empty.
+    pub metadata: Vec<MetricMetadata>,
+
+    /// The created timestamp, in unix milliseconds, of the series at the same index in `metrics`: when
+    /// its counter or histogram started counting from zero. 0, or a missing entry, means it has none,
+    /// e.g. for a gauge. Only Remote Write 2.0 carries them.
+    pub created_timestamps: Vec<i64>,
+
+    /// The remote write protocol version to use. Remote Write 2.0 falls back to 1.0 when the
+    /// receiver does not support it.
+    pub version: RemoteWriteVersion,
+}
+
+impl From<Vec<TimeSeries>> for RemoteWrite {
+    /// A Remote Write 1.0 push of the series, without metadata.
+    fn from(metrics: Vec<TimeSeries>) -> Self {
+        RemoteWrite {
+            metrics,
+            ..Default::default()
+        }
+    }
+}
+
 /// Sends Prometheus metrics to a Mimir remote write endpoint.
 ///
 /// # Arguments
 ///
 /// * `mimir_endpoint` - The base URL of your Mimir instance (e.g., "http://localhost:9009").
 /// * `tenant_id` - An optional tenant ID string for multi-tenant Mimir setups.
-/// * `metrics` - A vector of `TimeSeries` to send.
+/// * `write` - The `TimeSeries` to send, or a `RemoteWrite` to send them with metadata, created
+///   timestamps or Remote Write 2.0.
 pub async fn send_to_mimir(
     mimir_endpoint: &str,
     tenant_id: Option<&str>,
-    metrics: Vec<TimeSeries>,
+    write: impl Into<RemoteWrite>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if metrics.is_empty() {
+    let write = write.into();
+    if write.metrics.is_empty() {
         log::warn!("No metrics to send.");
         return Ok(());
     }
 
-    let write_request = WriteRequest {
-        timeseries: metrics,
-        ..Default::default() // Ensures forward compatibility with future fields
-    };
+    let url = mimir_endpoint;
+    let version = write.version;
+    let mut response = push(url, tenant_id, &write, version).await?;
+    if version == RemoteWriteVersion::V2 && response.status() == StatusCode::UNSUPPORTED_MEDIA_TYPE
+    {
+        log::warn!("Mimir at {url} does not accept Remote Write 2.0, falling back to 1.0");
+        response = push(url, tenant_id, &write, RemoteWriteVersion::V1).await?;
+    }
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        log::error!("Failed to push to Mimir: {} - {}", status, body);
+        return Err(Box::new(PushRejected { status, body }));
+    }
+    Ok(())
+}
+
+/// A push which Mimir answered with an error status.
+#[derive(Debug)]
+pub struct PushRejected {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl std::fmt::Display for PushRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to push to Mimir: {} - {}",
+            self.status, self.body
+        )
+    }
+}
 
+impl std::error::Error for PushRejected {}
+
+/// Whether a failed push may succeed when it is sent again, like Prometheus retries remote writes:
+/// when Mimir could not be reached, answered with a server error, or is rate limiting.
+pub fn is_retryable(error: &(dyn std::error::Error + 'static)) -> bool {
+    match error.downcast_ref::<PushRejected>() {
+        Some(rejected) => {
+            rejected.status.is_server_error() || rejected.status == StatusCode::TOO_MANY_REQUESTS
+        }
+        None => error.downcast_ref::<reqwest::Error>().is_some(),
+    }
+}
+
+/// The labels added to every series Oxybox writes, identifying the Oxybox instance.
+#[derive(Debug, Clone, Default)]
+pub struct ExternalLabels {
+    /// The `EXTERNAL_LABELS`, which give way to a label of the series with the same name.
+    pub labels: Vec<(String, String)>,
+
+    /// The `cluster` and `__replica__` labels of Mimir's HA tracker. These replace a label of the series
+    /// with the same name, as the deduplication of the replicas relies on them.
+    pub ha_labels: Vec<(String, String)>,
+}
+
+impl ExternalLabels {
+    /// Adds the external labels to every series.
+    pub fn apply(&self, metrics: &mut [TimeSeries]) {
+        for series in metrics.iter_mut() {
+            for (name, value) in &self.ha_labels {
+                series.labels.retain(|label| &label.name != name);
+                series.labels.push(Label {
+                    name: name.clone(),
+                    value: value.clone(),
+                });
+            }
+            for (name, value) in &self.labels {
+                if !series.labels.iter().any(|label| &label.name == name) {
+                    series.labels.push(Label {
+                        name: name.clone(),
+                        value: value.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.ha_labels.is_empty()
+    }
+}
+
+/// Encodes and compresses the metrics for the given protocol version and posts them to Mimir.
+async fn push(
+    mimir_endpoint: &str,
+    tenant_id: Option<&str>,
+    write: &RemoteWrite,
+    version: RemoteWriteVersion,
+) -> Result<Response, Box<dyn std::error::Error>> {
     let mut buf = Vec::new();
-    prost::Message::encode(&write_request, &mut buf)?;
+    match version {
+        RemoteWriteVersion::V1 => {
+            let write_request = WriteRequest {
+                timeseries: write.metrics.clone(),
+                metadata: write.metadata.clone(),
+            };
+            prost::Message::encode(&write_request, &mut buf)?;
+        }
+        RemoteWriteVersion::V2 => {
+            let write_request = to_write_v2_request(write);
+            prost::Message::encode(&write_request, &mut buf)?;
+        }
+    }
 
     let mut encoder = Encoder::new();
     let compressed_data = encoder.compress_vec(&buf)?;
@@ -45,11 +269,11 @@ pub async fn send_to_mimir(
     headers.insert(CONTENT_ENCODING, HeaderValue::from_static("snappy"));
     headers.insert(
         CONTENT_TYPE,
-        HeaderValue::from_static("application/x-protobuf"),
+        HeaderValue::from_static(version.content_type()),
     );
     headers.insert(
         "X-Prometheus-Remote-Write-Version",
-        HeaderValue::from_static("0.1.0"),
+        HeaderValue::from_static(version.version_header()),
     );
     if let Some(id) = tenant_id {
         headers.insert("X-Scope-OrgID", HeaderValue::from_str(id)?);
@@ -63,13 +287,164 @@ pub async fn send_to_mimir(
         .send()
         .await?;
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        log::error!("Failed to push to Mimir: {} - {}", status, body);
-        return Err(format!("Failed to push to Mimir: {status} - {body}").into());
+    Ok(response)
+}
+
+/// Interns strings into the symbol table of a Remote Write 2.0 request.
+/// The table always starts with the empty string, so optional references default to index 0.
+struct SymbolTable {
+    symbols: Vec<String>,
+    refs: HashMap<String, u32>,
+}
+
+impl SymbolTable {
+    fn new() -> Self {
+        let mut table = SymbolTable {
+            symbols: Vec::new(),
+            refs: HashMap::new(),
+        };
+        table.intern("");
+        table
+    }
+
+    fn intern(&mut self, symbol: &str) -> u32 {
+        if let Some(reference) = self.refs.get(symbol) {
+            return *reference;
+        }
+        let reference = self.symbols.len() as u32;
+        self.symbols.push(symbol.to_string());
+        self.refs.insert(symbol.to_string(), reference);
+        reference
+    }
+
+    fn intern_labels(&mut self, labels: &[Label]) -> Vec<u32> {
+        let mut sorted: Vec<&Label> = labels.iter().collect();
+        sorted.sort_by(|a, b| a.name.cmp(&b.name));
+        sorted
+            .into_iter()
+            .flat_map(|label| [self.intern(&label.name), self.intern(&label.value)])
+            .collect()
+    }
+}
+
+/// Converts Remote Write 1.0 time series into a Remote Write 2.0 request.
+/// Label names and values, help texts and units are de-duplicated into the request's symbol table.
+/// Metadata is attached to every series whose `__name__` matches a metric family in `metadata`, the
+/// created timestamp to every series which has one.
+/// # Arguments
+///     * `write` - The time series to convert, with their metadata and created timestamps.
+/// # Returns
+///     An `io.prometheus.write.v2.Request` containing the converted series and symbol table.
+pub fn to_write_v2_request(write: &RemoteWrite) -> v2::Request {
+    let mut symbols = SymbolTable::new();
+    let metadata_by_family: HashMap<&str, &MetricMetadata> = write
+        .metadata
+        .iter()
+        .map(|m| (m.metric_family_name.as_str(), m))
+        .collect();
+
+    let timeseries = write
+        .metrics
+        .iter()
+        .enumerate()
+        .map(|(index, series)| {
+            let metadata = series
+                .labels
+                .iter()
+                .find(|label| label.name == "__name__")
+                .and_then(|name| metadata_by_family.get(name.value.as_str()))
+                .map(|m| v2::Metadata {
+                    r#type: to_v2_metric_type(m.r#type()) as i32,
+                    help_ref: symbols.intern(&m.help),
+                    unit_ref: symbols.intern(&m.unit),
+                });
+
+            v2::TimeSeries {
+                labels_refs: symbols.intern_labels(&series.labels),
+                samples: series
+                    .samples
+                    .iter()
+                    .map(|s| v2::Sample {
+                        value: s.value,
+                        timestamp: s.timestamp,
+                    })
+                    .collect(),
+                histograms: series.histograms.iter().map(to_v2_histogram).collect(),
+                exemplars: series
+                    .exemplars
+                    .iter()
+                    .map(|e| to_v2_exemplar(e, &mut symbols))
+                    .collect(),
+                metadata,
+                created_timestamp: write
+                    .created_timestamps
+                    .get(index)
+                    .copied()
+                    .unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    v2::Request {
+        symbols: symbols.symbols,
+        timeseries,
+    }
+}
+
+fn to_v2_metric_type(metric_type: MetricType) -> v2::metadata::MetricType {
+    match metric_type {
+        MetricType::Unknown => v2::metadata::MetricType::Unspecified,
+        MetricType::Counter => v2::metadata::MetricType::Counter,
+        MetricType::Gauge => v2::metadata::MetricType::Gauge,
+        MetricType::Histogram => v2::metadata::MetricType::Histogram,
+        MetricType::Gaugehistogram => v2::metadata::MetricType::Gaugehistogram,
+        MetricType::Summary => v2::metadata::MetricType::Summary,
+        MetricType::Info => v2::metadata::MetricType::Info,
+        MetricType::Stateset => v2::metadata::MetricType::Stateset,
+    }
+}
+
+fn to_v2_exemplar(exemplar: &Exemplar, symbols: &mut SymbolTable) -> v2::Exemplar {
+    v2::Exemplar {
+        labels_refs: symbols.intern_labels(&exemplar.labels),
+        value: exemplar.value,
+        timestamp: exemplar.timestamp,
+    }
+}
+
+fn to_v2_histogram(h: &Histogram) -> v2::Histogram {
+    let to_v2_spans = |spans: &[prometheus::prompb::BucketSpan]| {
+        spans
+            .iter()
+            .map(|s| v2::BucketSpan {
+                offset: s.offset,
+                length: s.length,
+            })
+            .collect()
+    };
+
+    v2::Histogram {
+        count: h.count.map(|count| match count {
+            histogram::Count::CountInt(c) => v2::histogram::Count::CountInt(c),
+            histogram::Count::CountFloat(c) => v2::histogram::Count::CountFloat(c),
+        }),
+        sum: h.sum,
+        schema: h.schema,
+        zero_threshold: h.zero_threshold,
+        zero_count: h.zero_count.map(|count| match count {
+            histogram::ZeroCount::ZeroCountInt(c) => v2::histogram::ZeroCount::ZeroCountInt(c),
+            histogram::ZeroCount::ZeroCountFloat(c) => v2::histogram::ZeroCount::ZeroCountFloat(c),
+        }),
+        negative_spans: to_v2_spans(&h.negative_spans),
+        negative_deltas: h.negative_deltas.clone(),
+        negative_counts: h.negative_counts.clone(),
+        positive_spans: to_v2_spans(&h.positive_spans),
+        positive_deltas: h.positive_deltas.clone(),
+        positive_counts: h.positive_counts.clone(),
+        reset_hint: h.reset_hint,
+        timestamp: h.timestamp,
+        custom_values: h.custom_values.clone(),
     }
-    Ok(())
 }
 
 /// Creates a `TimeSeries` metric with the given metric name, labels, value, and optional timestamp.
@@ -119,6 +494,7 @@ pub fn create_time_series(
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[allow(clippy::vec_init_then_push, clippy::assertions_on_constants)]
     #[tokio::test]
 
     async fn test_create_and_send_metrics() {
@@ -161,4 +537,107 @@ mod tests {
 
         assert!(true);
     }
+
+    #[test]
+    fn test_apply_external_labels() {
+        let mut metrics = vec![create_time_series(
+            "probe_success",
+            &[("region", "us-east"), ("cluster", "from-target")],
+            1.0,
+            None,
+        )];
+        let external_labels = ExternalLabels {
+            labels: vec![("region".to_string(), "eu-west".to_string())],
+            ha_labels: vec![
+                ("cluster".to_string(), "oxybox".to_string()),
+                ("__replica__".to_string(), "replica-1".to_string()),
+            ],
+        };
+
+        external_labels.apply(&mut metrics);
+
+        let labels: Vec<(&str, &str)> = metrics[0]
+            .labels
+            .iter()
+            .map(|l| (l.name.as_str(), l.value.as_str()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("__name__", "probe_success"),
+                // existing labels take precedence over external labels, but not over the HA labels
+                ("region", "us-east"),
+                ("cluster", "oxybox"),
+                ("__replica__", "replica-1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remote_write_version_from_str() {
+        assert_eq!("1".parse(), Ok(RemoteWriteVersion::V1));
+        assert_eq!("2.0".parse(), Ok(RemoteWriteVersion::V2));
+        assert_eq!(" V2 ".parse(), Ok(RemoteWriteVersion::V2));
+        assert!("3".parse::<RemoteWriteVersion>().is_err());
+        let version: RemoteWriteVersion = serde_yaml::from_str("2").unwrap();
+        assert_eq!(version, RemoteWriteVersion::V2);
+        let version: RemoteWriteVersion = serde_yaml::from_str("\"1.0\"").unwrap();
+        assert_eq!(version, RemoteWriteVersion::V1);
+    }
+
+    #[test]
+    fn test_to_write_v2_request_interns_symbols() {
+        let metrics = vec![
+            create_time_series("probe_success", &[("job", "oxybox")], 1.0, Some(1000)),
+            create_time_series(
+                "probe_duration_seconds",
+                &[("job", "oxybox")],
+                0.5,
+                Some(1000),
+            ),
+        ];
+        let metadata = vec![MetricMetadata {
+            r#type: MetricType::Gauge as i32,
+            metric_family_name: "probe_success".to_string(),
+            help: "Displays whether or not the probe was a success".to_string(),
+            unit: String::new(),
+        }];
+
+        let request = to_write_v2_request(&RemoteWrite {
+            metrics,
+            metadata,
+            created_timestamps: vec![0, 500],
+            version: RemoteWriteVersion::V2,
+        });
+
+        assert_eq!(request.symbols[0], "");
+        // "job" and "oxybox" are shared by both series and only interned once
+        assert_eq!(request.symbols.iter().filter(|s| *s == "job").count(), 1);
+        assert_eq!(request.timeseries.len(), 2);
+
+        // labels are sorted by name: __name__ before job
+        let first = &request.timeseries[0];
+        let labels: Vec<&str> = first
+            .labels_refs
+            .iter()
+            .map(|r| request.symbols[*r as usize].as_str())
+            .collect();
+        assert_eq!(labels, vec!["__name__", "probe_success", "job", "oxybox"]);
+        assert_eq!(first.samples[0].value, 1.0);
+        assert_eq!(first.samples[0].timestamp, 1000);
+
+        let metadata = first.metadata.expect("Metadata missing");
+        assert_eq!(metadata.r#type(), v2::metadata::MetricType::Gauge);
+        assert_eq!(
+            request.symbols[metadata.help_ref as usize],
+            "Displays whether or not the probe was a success"
+        );
+        assert_eq!(metadata.unit_ref, 0);
+
+        // series without registered metadata carry none
+        assert!(request.timeseries[1].metadata.is_none());
+
+        assert_eq!(request.timeseries[0].created_timestamp, 0);
+        assert_eq!(request.timeseries[1].created_timestamp, 500);
+    }
 }
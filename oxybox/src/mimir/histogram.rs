@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use chrono::Utc;
+
+use super::client::prometheus::prompb::{BucketSpan, Histogram, TimeSeries, histogram};
+use crate::config::probe_config::{NativeHistogramsConfig, OrganisationConfig};
+use crate::http_probe::result::ProbeResult;
+
+/// The default zero threshold of Prometheus native histograms (2^-128).
+const DEFAULT_ZERO_THRESHOLD: f64 = 2.938735877055719e-39;
+
+/// A Prometheus native (sparse) histogram with exponential buckets.
+/// Bucket boundaries are powers of `2^(2^-schema)`, so only buckets that received observations are
+/// stored and sent, while the relative error of a quantile stays bounded by the schema.
+#[derive(Debug, Clone)]
+pub struct NativeHistogram {
+    schema: i32,
+    zero_threshold: f64,
+    zero_count: u64,
+    count: u64,
+    sum: f64,
+    buckets: BTreeMap<i32, u64>,
+}
+
+impl NativeHistogram {
+    /// Creates an empty histogram for the given schema, between -4 (base 2^16) and 8 (base 2^(1/256)).
+    pub fn new(schema: i32) -> Self {
+        NativeHistogram {
+            schema: schema.clamp(-4, 8),
+            zero_threshold: DEFAULT_ZERO_THRESHOLD,
+            zero_count: 0,
+            count: 0,
+            sum: 0.0,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    /// Records a single observation.
+    pub fn observe(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.count += 1;
+        self.sum += value;
+        if value.abs() <= self.zero_threshold {
+            self.zero_count += 1;
+        } else {
+            // probe durations are never negative, so negative buckets are not tracked
+            *self
+                .buckets
+                .entry(self.bucket_index(value.abs()))
+                .or_default() += 1;
+        }
+    }
+
+    /// Returns the index of the bucket `(base^(index-1), base^index]` holding the given positive value.
+    /// This follows the Prometheus client libraries, splitting the value in a fraction and exponent
+    /// to avoid rounding errors at the bucket boundaries.
+    fn bucket_index(&self, value: f64) -> i32 {
+        let (frac, exp) = frexp(value);
+        if self.schema > 0 {
+            let buckets_per_power = 1 << self.schema;
+            // the first bucket boundary above `frac` within [0.5, 1)
+            let offset = (0..buckets_per_power)
+                .find(|i| {
+                    let bound = 2f64.powf(*i as f64 / buckets_per_power as f64) / 2.0;
+                    bound >= frac
+                })
+                .unwrap_or(buckets_per_power);
+            offset + (exp - 1) * buckets_per_power
+        } else {
+            let mut index = exp;
+            if frac == 0.5 {
+                index -= 1;
+            }
+            let shift = -self.schema;
+            (index + (1 << shift) - 1) >> shift
+        }
+    }
+
+    /// Converts the histogram into its remote write representation, with buckets delta-encoded in spans.
+    pub fn to_proto(&self, timestamp_ms: i64) -> Histogram {
+        let mut positive_spans: Vec<BucketSpan> = Vec::new();
+        let mut positive_deltas = Vec::with_capacity(self.buckets.len());
+        let mut previous_index: Option<i32> = None;
+        let mut previous_count: i64 = 0;
+
+        for (index, count) in &self.buckets {
+            match (previous_index, positive_spans.last_mut()) {
+                (Some(previous), Some(span)) if *index == previous + 1 => span.length += 1,
+                (Some(previous), _) => positive_spans.push(BucketSpan {
+                    offset: index - previous - 1,
+                    length: 1,
+                }),
+                (None, _) => positive_spans.push(BucketSpan {
+                    offset: *index,
+                    length: 1,
+                }),
+            }
+            positive_deltas.push(*count as i64 - previous_count);
+            previous_count = *count as i64;
+            previous_index = Some(*index);
+        }
+
+        Histogram {
+            count: Some(histogram::Count::CountInt(self.count)),
+            sum: self.sum,
+            schema: self.schema,
+            zero_threshold: self.zero_threshold,
+            zero_count: Some(histogram::ZeroCount::ZeroCountInt(self.zero_count)),
+            positive_spans,
+            positive_deltas,
+            timestamp: timestamp_ms,
+            ..Default::default()
+        }
+    }
+}
+
+/// Splits a positive, normal float into a fraction in `[0.5, 1)` and a power of two exponent.
+fn frexp(value: f64) -> (f64, i32) {
+    let bits = value.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32 - 1022;
+    let fraction = f64::from_bits((bits & !(0x7ff << 52)) | (1022 << 52));
+    (fraction, exponent)
+}
+
+/// A histogram in the registry, identified by the URL of its target and its phase.
+type HistogramKey = (String, &'static str);
+
+/// Accumulates the phase durations of every target of a tenant into native histograms.
+/// The histograms are cumulative, just like the histograms of a Prometheus client library, so
+/// percentiles over any range are computed with `histogram_quantile` over a `rate`.
+pub struct PhaseHistograms {
+    schema: i32,
+    keep_phase_gauges: bool,
+    /// The histogram of every target and phase, with the unix timestamp in milliseconds it was
+    /// created at, sent along as its created timestamp.
+    histograms: Mutex<HashMap<HistogramKey, (NativeHistogram, i64)>>,
+}
+
+impl PhaseHistograms {
+    pub fn new(config: &NativeHistogramsConfig) -> Self {
+        PhaseHistograms {
+            schema: config.schema,
+            keep_phase_gauges: config.keep_phase_gauges,
+            histograms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the `probe_http_duration_seconds` gauges are pushed next to the histograms.
+    pub fn keep_phase_gauges(&self) -> bool {
+        self.keep_phase_gauges
+    }
+
+    /// Records the phase durations of a probe in the histograms of its target.
+    pub fn observe(&self, probe_result: &ProbeResult) {
+        let mut histograms = self.histograms.lock().unwrap();
+        for (duration, phase) in super::probe_phases(probe_result) {
+            if let Some(duration) = duration {
+                histograms
+                    .entry((probe_result.url.clone(), phase))
+                    .or_insert_with(|| {
+                        (
+                            NativeHistogram::new(self.schema),
+                            Utc::now().timestamp_millis(),
+                        )
+                    })
+                    .0
+                    .observe(duration);
+            }
+        }
+    }
+
+    /// Forgets the histograms of a target, e.g. once it is no longer probed.
+    pub fn remove_target(&self, url: &str) {
+        self.histograms
+            .lock()
+            .unwrap()
+            .retain(|(target, _), _| target != url);
+    }
+
+    /// Creates a native histogram series for every target and phase observed so far, labeled like the
+    /// other series of the target. The histograms of targets which are not in the organisation are
+    /// forgotten instead.
+    /// # Returns
+    ///     The series, and the created timestamp of every series: when its histogram was created.
+    pub fn series(
+        &self,
+        timestamp_ms: i64,
+        org_config: &OrganisationConfig,
+    ) -> (Vec<TimeSeries>, Vec<i64>) {
+        let mut histograms = self.histograms.lock().unwrap();
+        histograms.retain(|(url, _), _| org_config.targets.iter().any(|target| &target.url == url));
+
+        let mut all_series = Vec::new();
+        let mut created_timestamps = Vec::new();
+        for ((url, phase), (histogram, created_ms)) in histograms.iter() {
+            let Some(target) = org_config.targets.iter().find(|target| &target.url == url) else {
+                continue;
+            };
+            let mut series = vec![super::create_phase_histogram_series(
+                url,
+                phase,
+                histogram.to_proto(timestamp_ms),
+            )];
+            super::label_series(&mut series, target, org_config);
+            created_timestamps.extend(series.iter().map(|_| *created_ms));
+            all_series.extend(series);
+        }
+        (all_series, created_timestamps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::probe_config::TargetConfig;
+    use crate::http_probe::trace::TraceContext;
+
+    #[test]
+    fn test_bucket_index() {
+        let schema_0 = NativeHistogram::new(0);
+        // schema 0 buckets are (0.5, 1], (1, 2], (2, 4], ...
+        assert_eq!(schema_0.bucket_index(1.0), 0);
+        assert_eq!(schema_0.bucket_index(1.5), 1);
+        assert_eq!(schema_0.bucket_index(2.0), 1);
+        assert_eq!(schema_0.bucket_index(0.3), -1);
+
+        let schema_3 = NativeHistogram::new(3);
+        // schema 3 has 8 buckets per power of two
+        assert_eq!(schema_3.bucket_index(1.0), 0);
+        assert_eq!(schema_3.bucket_index(2.0), 8);
+        assert_eq!(schema_3.bucket_index(1.05), 1);
+        assert_eq!(schema_3.bucket_index(0.5), -8);
+
+        let schema_minus_1 = NativeHistogram::new(-1);
+        // schema -1 buckets are (0.25, 1], (1, 4], ...
+        assert_eq!(schema_minus_1.bucket_index(1.0), 0);
+        assert_eq!(schema_minus_1.bucket_index(3.0), 1);
+        assert_eq!(schema_minus_1.bucket_index(4.0), 1);
+        assert_eq!(schema_minus_1.bucket_index(4.5), 2);
+    }
+
+    #[test]
+    fn test_to_proto_encodes_spans_and_deltas() {
+        let mut histogram = NativeHistogram::new(0);
+        for value in [1.0, 1.5, 1.5, 3.0, 20.0, 0.0] {
+            histogram.observe(value);
+        }
+
+        let proto = histogram.to_proto(1000);
+        assert_eq!(proto.count, Some(histogram::Count::CountInt(6)));
+        assert_eq!(proto.sum, 27.0);
+        assert_eq!(
+            proto.zero_count,
+            Some(histogram::ZeroCount::ZeroCountInt(1))
+        );
+        // buckets 0, 1, 2 hold 1, 2, 1 observations, bucket 5 holds 1
+        assert_eq!(
+            proto.positive_spans,
+            vec![
+                BucketSpan {
+                    offset: 0,
+                    length: 3
+                },
+                BucketSpan {
+                    offset: 2,
+                    length: 1
+                },
+            ]
+        );
+        assert_eq!(proto.positive_deltas, vec![1, 1, -1, 0]);
+        assert_eq!(proto.timestamp, 1000);
+    }
+
+    #[test]
+    fn test_series_forgets_removed_targets() {
+        let histograms = PhaseHistograms::new(&NativeHistogramsConfig {
+            window_seconds: 60,
+            schema: 3,
+            keep_phase_gauges: false,
+        });
+        for url in [
+            "https://a.example.com",
+            "https://b.example.com",
+            "https://c.example.com",
+        ] {
+            let mut probe = ProbeResult::failed(url, 0, TraceContext::new());
+            probe.dns_time = Some(0.01);
+            histograms.observe(&probe);
+        }
+        let org_config = OrganisationConfig {
+            organisation_id: "org-1".to_string(),
+            polling_interval_seconds: 10,
+            targets: ["https://a.example.com", "https://b.example.com"]
+                .map(|url| TargetConfig {
+                    url: url.to_string(),
+                    ..Default::default()
+                })
+                .to_vec(),
+            native_histograms: None,
+            sinks: vec![],
+            job_name: "oxybox".to_string(),
+            instance_label: Default::default(),
+            metric_prefix: String::new(),
+            labels: Default::default(),
+            defaults: Default::default(),
+            relabel_configs: vec![],
+            metric_relabel_configs: vec![],
+        };
+        histograms.remove_target("https://b.example.com");
+
+        let (series, created_timestamps) = histograms.series(1000, &org_config);
+        assert_eq!(series.len(), 1);
+        assert_eq!(created_timestamps.len(), 1);
+        assert!(created_timestamps[0] > 0);
+        assert!(
+            series[0]
+                .labels
+                .iter()
+                .any(|label| label.value == "https://a.example.com")
+        );
+        assert_eq!(histograms.histograms.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,166 @@
+use std::time::{Duration, Instant};
+
+use super::client::prometheus::prompb::{MetricMetadata, metric_metadata::MetricType};
+use super::{
+    PROBE_DNS_LOOKUP_TIME_METRIC, PROBE_DURATION_METRIC, PROBE_HTTP_DURATION_METRIC,
+    PROBE_HTTP_PHASE_HISTOGRAM_METRIC, PROBE_HTTP_SSL_EARLIEST_EXPIERY_METRIC,
+    PROBE_HTTP_SSL_ENABLED_METRIC, PROBE_HTTP_STATUS_METRIC, PROBE_HTTP_VERSION_METRIC,
+    PROBE_SUCCESS_METRIC,
+};
+
+/// The metadata registry of every metric family emitted by Oxybox.
+/// Each entry holds the metric family name, its type, the HELP text and the UNIT.
+/// The HELP texts match the ones of the Blackbox Exporter, so existing dashboards read the same.
+const PROBE_METRIC_METADATA: &[(&str, MetricType, &str, &str)] = &[
+    (
+        PROBE_SUCCESS_METRIC,
+        MetricType::Gauge,
+        "Displays whether or not the probe was a success",
+        "",
+    ),
+    (
+        PROBE_DURATION_METRIC,
+        MetricType::Gauge,
+        "Returns how long the probe took to complete in seconds",
+        "seconds",
+    ),
+    (
+        PROBE_HTTP_STATUS_METRIC,
+        MetricType::Gauge,
+        "Response HTTP status code",
+        "",
+    ),
+    (
+        PROBE_HTTP_DURATION_METRIC,
+        MetricType::Gauge,
+        "Duration of http request by phase, summed over all redirects",
+        "seconds",
+    ),
+    (
+        PROBE_DNS_LOOKUP_TIME_METRIC,
+        MetricType::Gauge,
+        "Returns the time taken for probe dns lookup in seconds",
+        "seconds",
+    ),
+    (
+        PROBE_HTTP_SSL_ENABLED_METRIC,
+        MetricType::Gauge,
+        "Indicates if SSL was used for the final redirect",
+        "",
+    ),
+    (
+        PROBE_HTTP_VERSION_METRIC,
+        MetricType::Gauge,
+        "Returns the version of HTTP of the probe response",
+        "",
+    ),
+    (
+        PROBE_HTTP_SSL_EARLIEST_EXPIERY_METRIC,
+        MetricType::Gauge,
+        "Returns last SSL chain expiry in unixtime",
+        "",
+    ),
+    (
+        PROBE_HTTP_PHASE_HISTOGRAM_METRIC,
+        MetricType::Histogram,
+        "Native histogram of the duration of http requests by phase",
+        "seconds",
+    ),
+];
+
+/// Returns the metadata (HELP, TYPE and UNIT) of every probe metric, ready to be sent with a remote write.
+pub fn probe_metadata() -> Vec<MetricMetadata> {
+    prefixed_probe_metadata("")
+}
+
+/// Returns the metadata of every probe metric, for metric names with the given prefix.
+/// # Arguments
+///     * `prefix` - The metric prefix of the organisation, may be empty.
+pub fn prefixed_probe_metadata(prefix: &str) -> Vec<MetricMetadata> {
+    PROBE_METRIC_METADATA
+        .iter()
+        .map(|(name, metric_type, help, unit)| MetricMetadata {
+            r#type: *metric_type as i32,
+            metric_family_name: format!("{prefix}{name}"),
+            help: help.to_string(),
+            unit: unit.to_string(),
+        })
+        .collect()
+}
+
+/// Finds the metadata of a metric by its exact name.
+/// # Arguments
+///     * `metadata` - The metadata of the metric families, e.g. `prefixed_probe_metadata(prefix)`
+///       for the metrics of an organisation with a metric prefix.
+///     * `name` - The name of the metric.
+pub fn find_metadata<'a>(metadata: &'a [MetricMetadata], name: &str) -> Option<&'a MetricMetadata> {
+    metadata.iter().find(|m| m.metric_family_name == name)
+}
+
+/// Keeps track of when metric metadata was last sent, so it is only attached to a write once per interval.
+/// This mirrors Prometheus, which sends metadata on a fixed interval instead of with every sample.
+pub struct MetadataSchedule {
+    interval: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl MetadataSchedule {
+    /// Creates a schedule sending metadata every `interval`. A zero interval disables sending metadata.
+    pub fn new(interval: Duration) -> Self {
+        MetadataSchedule {
+            interval,
+            last_sent: None,
+        }
+    }
+
+    /// Returns `true` when metadata should be sent now, and marks it as sent.
+    pub fn due(&mut self) -> bool {
+        if self.interval.is_zero() {
+            return false;
+        }
+        let due = self
+            .last_sent
+            .map(|last_sent| last_sent.elapsed() >= self.interval)
+            .unwrap_or(true);
+        if due {
+            self.last_sent = Some(Instant::now());
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_metadata_covers_all_metrics() {
+        let metadata = probe_metadata();
+        assert_eq!(metadata.len(), PROBE_METRIC_METADATA.len());
+        for m in &metadata {
+            assert!(
+                !m.help.is_empty(),
+                "{} has no help text",
+                m.metric_family_name
+            );
+            let expected_type = match m.metric_family_name.as_str() {
+                PROBE_HTTP_PHASE_HISTOGRAM_METRIC => MetricType::Histogram,
+                _ => MetricType::Gauge,
+            };
+            assert_eq!(m.r#type(), expected_type);
+            if m.metric_family_name.ends_with("_seconds") {
+                assert_eq!(m.unit, "seconds");
+            }
+        }
+    }
+
+    #[test]
+    fn test_metadata_schedule() {
+        let mut schedule = MetadataSchedule::new(Duration::from_secs(60));
+        assert!(schedule.due());
+        assert!(!schedule.due());
+
+        let mut disabled = MetadataSchedule::new(Duration::ZERO);
+        assert!(!disabled.due());
+    }
+}
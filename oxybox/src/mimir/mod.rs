@@ -1,13 +1,20 @@
 use client::prometheus::prompb;
 
+use crate::config::probe_config::{OrganisationConfig, TargetConfig};
 use crate::http_probe::result::ProbeResult;
 
 pub mod client;
+pub mod histogram;
+pub mod metadata;
+pub mod relabel;
+pub mod staleness;
 
 const INSTANCE_LABEL: &str = "instance";
 const JOB_LABEL: &str = "job";
 const MODULE_LABEL: &str = "module";
 const TARGET_LABEL: &str = "target";
+/// The label holding the organisation ID of a series on the `/metrics` endpoint of all tenants.
+pub const TENANT_LABEL: &str = "tenant";
 const PROBE_SUCCESS_METRIC: &str = "probe_success";
 const PROBE_DURATION_METRIC: &str = "probe_duration_seconds";
 const PROBE_HTTP_STATUS_METRIC: &str = "probe_http_status_code";
@@ -16,14 +23,29 @@ const PROBE_DNS_LOOKUP_TIME_METRIC: &str = "probe_dns_lookup_time_seconds";
 const PROBE_HTTP_SSL_ENABLED_METRIC: &str = "probe_http_ssl";
 const PROBE_HTTP_VERSION_METRIC: &str = "probe_http_version";
 const PROBE_HTTP_SSL_EARLIEST_EXPIERY_METRIC: &str = "probe_ssl_earliest_cert_expiry";
+const PROBE_HTTP_PHASE_HISTOGRAM_METRIC: &str = "probe_http_phase_duration_seconds";
+const PHASE_LABEL: &str = "phase";
+const TRACE_ID_LABEL: &str = "trace_id";
+const SPAN_ID_LABEL: &str = "span_id";
 
-const BLACKBOX_JOB: &str = "oxybox";
-const HTTP_MODULE: &str = "http_probe";
+pub const BLACKBOX_JOB: &str = "oxybox";
+pub const HTTP_MODULE: &str = "http_probe";
+
+/// The labels Oxybox sets on the probe series, which cannot be set in the probe configuration.
+pub const RESERVED_LABELS: &[&str] = &[
+    INSTANCE_LABEL,
+    JOB_LABEL,
+    MODULE_LABEL,
+    TARGET_LABEL,
+    PHASE_LABEL,
+    TENANT_LABEL,
+];
 
 fn create_time_series(
     metric_name: &str,
     instance: &str,
     value: f64,
+    timestamp_ms: i64,
     additional_labels: Option<Vec<(&str, &str)>>,
 ) -> prompb::TimeSeries {
     let mut labels: Vec<(&str, &str)> = vec![
@@ -36,13 +58,129 @@ fn create_time_series(
         let _ = &labels.extend(iter);
     };
 
-    client::create_time_series(metric_name, &labels, value, None)
+    client::create_time_series(metric_name, &labels, value, Some(timestamp_ms))
+}
+
+/// Creates a native histogram series of the durations of a single phase of a target.
+fn create_phase_histogram_series(
+    instance: &str,
+    phase: &str,
+    histogram: prompb::Histogram,
+) -> prompb::TimeSeries {
+    let mut series = create_time_series(
+        PROBE_HTTP_PHASE_HISTOGRAM_METRIC,
+        instance,
+        0.0,
+        histogram.timestamp,
+        Some(vec![(PHASE_LABEL, phase)]),
+    );
+    series.samples.clear();
+    series.histograms.push(histogram);
+    series
+}
+
+/// Returns the duration of every phase of the probe, paired with the phase name, in the order they
+/// are performed.
+pub(crate) fn probe_phases(probe_result: &ProbeResult) -> [(Option<f64>, &'static str); 5] {
+    [
+        (probe_result.dns_time, "resolve"),
+        (probe_result.connect_time, "connect"),
+        (probe_result.tls_time, "tls"),
+        (probe_result.processing_time, "processing"),
+        (probe_result.transfer_time, "transfer"),
+    ]
+}
+
+/// Removes the `probe_http_duration_seconds` gauges, for when the phase durations are pushed as
+/// native histograms instead.
+pub fn remove_phase_duration_gauges(metrics: &mut Vec<prompb::TimeSeries>) {
+    metrics.retain(|series| {
+        !series
+            .labels
+            .iter()
+            .any(|label| label.name == "__name__" && label.value == PROBE_HTTP_DURATION_METRIC)
+    });
+}
+
+/// Removes the `instance`, `job`, `module` and `target` labels, for when the series are scraped from
+/// the `/probe` endpoint and Prometheus attaches these labels itself, just like with Blackbox Exporter.
+pub fn remove_target_labels(metrics: &mut [prompb::TimeSeries]) {
+    for series in metrics {
+        series.labels.retain(|label| {
+            ![INSTANCE_LABEL, JOB_LABEL, MODULE_LABEL, TARGET_LABEL].contains(&label.name.as_str())
+        });
+    }
+}
+
+/// Applies the settings of a target and its organisation to the series of the target: the module and
+/// labels of the target are added, the job, instance and metric names are set as configured for the
+/// organisation, after which the relabeling rules of the organisation are applied.
+/// # Arguments
+///     * `metrics` - The series of the target, created by `create_probe_metrics`.
+///     * `target` - The target the series belong to.
+///     * `org_config` - The organisation of the target.
+pub fn label_series(
+    metrics: &mut Vec<prompb::TimeSeries>,
+    target: &TargetConfig,
+    org_config: &OrganisationConfig,
+) {
+    add_target_labels(metrics, target);
+    apply_organisation_naming(metrics, target, org_config);
+    relabel::relabel_series(
+        metrics,
+        &target.url,
+        &org_config.relabel_configs,
+        &org_config.metric_relabel_configs,
+    );
+}
+
+/// Sets the `job` and `instance` labels and prefixes the metric names, as configured for the
+/// organisation. The `target` label keeps the URL of the target. The defaults leave the series as
+/// `create_probe_metrics` creates them.
+fn apply_organisation_naming(
+    metrics: &mut [prompb::TimeSeries],
+    target: &TargetConfig,
+    org_config: &OrganisationConfig,
+) {
+    let instance = org_config.instance_label.value(target);
+    for series in metrics {
+        for label in &mut series.labels {
+            match label.name.as_str() {
+                "__name__" if !org_config.metric_prefix.is_empty() => {
+                    label.value = format!("{}{}", org_config.metric_prefix, label.value);
+                }
+                JOB_LABEL => label.value.clone_from(&org_config.job_name),
+                INSTANCE_LABEL => label.value = instance.to_string(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Applies the settings of a target to its series: the `module` label is set to the module of the
+/// target, and the labels of the target are added. Labels the series already have are kept.
+fn add_target_labels(metrics: &mut [prompb::TimeSeries], target: &TargetConfig) {
+    for series in metrics {
+        for label in &mut series.labels {
+            if label.name == MODULE_LABEL {
+                label.value = target.module().to_string();
+            }
+        }
+        for (name, value) in &target.labels {
+            if !series.labels.iter().any(|label| &label.name == name) {
+                series.labels.push(prompb::Label {
+                    name: name.clone(),
+                    value: value.clone(),
+                });
+            }
+        }
+    }
 }
 
 /// Creates a vector of TimeSeries metrics for the given probe result.
 /// The metrics include:
 ///    - `probe_success`: Indicates if the probe was successful (1.0 for success, 0.0 for failure).
-///    - `probe_duration_seconds`: Total time taken for the probe.
+///    - `probe_duration_seconds`: Total time taken for the probe, with the trace ID of the probe request as exemplar.
 ///    - `probe_http_status_code`: HTTP status code received from the probe.
 ///    - `probe_http_duration_seconds`: Duration of various phases of the HTTP probe (resolve, connect, tls, processing, transfer).
 ///    - `probe_dns_lookup_time_seconds`: Time taken for DNS lookup.
@@ -52,12 +190,14 @@ fn create_time_series(
 /// ## Arguments:
 ///     - `probe_result`: A reference to the `ProbeResult` struct containing the results of the probe.
 ///     - `probe_success`: A boolean indicating whether the probe was successful or not.
+///     - `timestamp_ms`: The timestamp in unix milliseconds shared by all samples of the probe.
 /// ## Returns:
 ///     A vector of `prompb::TimeSeries` metrics representing the probe results, which can be sent
 ///     to a Prometheus-compatible monitoring system.
 pub fn create_probe_metrics(
     probe_result: &ProbeResult,
     probe_success: bool,
+    timestamp_ms: i64,
 ) -> Vec<prompb::TimeSeries> {
     let mut metrics = Vec::new();
     let probe_successful = match probe_success {
@@ -69,39 +209,50 @@ pub fn create_probe_metrics(
         PROBE_SUCCESS_METRIC,
         &probe_result.url,
         probe_successful,
+        timestamp_ms,
         None,
     ));
 
-    let phases = [
-        (probe_result.dns_time, "resolve"),
-        (probe_result.connect_time, "connect"),
-        (probe_result.tls_time, "tls"),
-        (probe_result.processing_time, "processing"),
-        (probe_result.transfer_time, "transfer"),
-    ];
-
-    for (duration_opt, phase) in phases.iter() {
+    for (duration_opt, phase) in probe_phases(probe_result).iter() {
         if let Some(duration) = duration_opt {
             metrics.push(create_time_series(
                 PROBE_HTTP_DURATION_METRIC,
                 &probe_result.url,
                 *duration,
-                Some(vec![("phase", *phase)]),
+                timestamp_ms,
+                Some(vec![(PHASE_LABEL, *phase)]),
             ));
         }
     }
 
-    metrics.push(create_time_series(
+    let mut duration_series = create_time_series(
         PROBE_DURATION_METRIC,
         &probe_result.url,
         probe_result.total_probe_time,
+        timestamp_ms,
         None,
-    ));
+    );
+    duration_series.exemplars.push(prompb::Exemplar {
+        labels: vec![
+            prompb::Label {
+                name: TRACE_ID_LABEL.to_string(),
+                value: probe_result.trace_context.trace_id_hex(),
+            },
+            prompb::Label {
+                name: SPAN_ID_LABEL.to_string(),
+                value: probe_result.trace_context.span_id_hex(),
+            },
+        ],
+        value: probe_result.total_probe_time,
+        timestamp: timestamp_ms,
+    });
+    metrics.push(duration_series);
     if let Some(http_status) = probe_result.http_status {
         metrics.push(create_time_series(
             PROBE_HTTP_STATUS_METRIC,
             &probe_result.url,
             http_status as f64,
+            timestamp_ms,
             None,
         ));
     }
@@ -111,6 +262,7 @@ pub fn create_probe_metrics(
             PROBE_DNS_LOOKUP_TIME_METRIC,
             &probe_result.url,
             dns_time,
+            timestamp_ms,
             None,
         ));
     }
@@ -124,6 +276,7 @@ pub fn create_probe_metrics(
         PROBE_HTTP_SSL_ENABLED_METRIC,
         &probe_result.url,
         ssl_enabled,
+        timestamp_ms,
         None,
     ));
 
@@ -132,6 +285,7 @@ pub fn create_probe_metrics(
             PROBE_HTTP_SSL_EARLIEST_EXPIERY_METRIC,
             &probe_result.url,
             cert_validity_seconds,
+            timestamp_ms,
             None,
         ));
     }
@@ -140,6 +294,7 @@ pub fn create_probe_metrics(
             PROBE_HTTP_VERSION_METRIC,
             &probe_result.url,
             http_version,
+            timestamp_ms,
             None,
         ));
     }
@@ -0,0 +1,434 @@
+use std::fmt;
+
+use md5::{Digest, Md5};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use url::Url;
+
+use super::PHASE_LABEL;
+use super::client::prometheus::prompb::{Label, TimeSeries};
+
+/// What a relabeling rule does, following Prometheus.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelabelAction {
+    /// Sets `target_label` to `replacement`, with the groups of `regex` expanded, when `regex`
+    /// matches the source labels.
+    #[default]
+    Replace,
+
+    /// Drops the series when `regex` does not match the source labels.
+    Keep,
+
+    /// Drops the series when `regex` matches the source labels.
+    Drop,
+
+    /// Copies every label whose name matches `regex` to the name given by `replacement`.
+    Labelmap,
+
+    /// Sets `target_label` to the MD5 hash of the source labels, modulo `modulus`.
+    Hashmod,
+
+    /// Sets `target_label` to the source labels, lowercased.
+    Lowercase,
+}
+
+impl fmt::Display for RelabelAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RelabelAction::Replace => "replace",
+            RelabelAction::Keep => "keep",
+            RelabelAction::Drop => "drop",
+            RelabelAction::Labelmap => "labelmap",
+            RelabelAction::Hashmod => "hashmod",
+            RelabelAction::Lowercase => "lowercase",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A regular expression of a relabeling rule, anchored at both ends like in Prometheus.
+#[derive(Debug, Clone)]
+pub struct RelabelRegex {
+    /// The expression as configured, without the anchors.
+    source: String,
+    regex: Regex,
+}
+
+impl RelabelRegex {
+    /// Compiles an expression, anchoring it at both ends.
+    /// # Errors
+    ///     Returns an error if the expression is not a valid regular expression.
+    pub fn new(source: &str) -> Result<Self, regex::Error> {
+        Ok(RelabelRegex {
+            source: source.to_string(),
+            regex: Regex::new(&format!("^(?:{source})$"))?,
+        })
+    }
+}
+
+impl Default for RelabelRegex {
+    fn default() -> Self {
+        RelabelRegex::new("(.*)").expect("The default regex is valid")
+    }
+}
+
+impl PartialEq for RelabelRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl<'de> Deserialize<'de> for RelabelRegex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        RelabelRegex::new(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for RelabelRegex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.source.serialize(serializer)
+    }
+}
+
+/// A Prometheus-style relabeling rule, rewriting the labels of a series or dropping it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RelabelConfig {
+    /// The labels whose values are joined with `separator` and matched against `regex`.
+    #[serde(default)]
+    pub source_labels: Vec<String>,
+
+    /// The separator between the values of the source labels. Defaults to `;`.
+    #[serde(default = "default_separator")]
+    pub separator: String,
+
+    /// The label written by the `replace`, `hashmod` and `lowercase` actions.
+    /// For `replace`, the groups of `regex` can be used, e.g. `${1}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_label: Option<String>,
+
+    /// The expression matched against the source labels. Defaults to `(.*)`.
+    #[serde(default)]
+    pub regex: RelabelRegex,
+
+    /// The modulus of the `hashmod` action.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modulus: Option<u64>,
+
+    /// The value written by `replace`, or the label name written by `labelmap`. Defaults to `$1`.
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+
+    /// What the rule does. Defaults to `replace`.
+    #[serde(default)]
+    pub action: RelabelAction,
+}
+
+fn default_separator() -> String {
+    ";".to_string()
+}
+
+fn default_replacement() -> String {
+    "$1".to_string()
+}
+
+impl RelabelConfig {
+    /// Applies the rule to a label set.
+    /// # Returns
+    ///     False when the label set is dropped.
+    fn apply(&self, labels: &mut Vec<Label>) -> bool {
+        let value = self
+            .source_labels
+            .iter()
+            .map(|name| label_value(labels, name))
+            .collect::<Vec<_>>()
+            .join(&self.separator);
+        let regex = &self.regex.regex;
+        let target_label = self.target_label.as_deref().unwrap_or_default();
+
+        match self.action {
+            RelabelAction::Keep => return regex.is_match(&value),
+            RelabelAction::Drop => return !regex.is_match(&value),
+            RelabelAction::Replace => {
+                let Some(captures) = regex.captures(&value) else {
+                    return true;
+                };
+                let mut name = String::new();
+                captures.expand(target_label, &mut name);
+                if !is_label_name(&name) {
+                    return true;
+                }
+                let mut replacement = String::new();
+                captures.expand(&self.replacement, &mut replacement);
+                set_label(labels, &name, replacement);
+            }
+            RelabelAction::Labelmap => {
+                let mapped: Vec<(String, String)> = labels
+                    .iter()
+                    .filter(|label| regex.is_match(&label.name))
+                    .map(|label| {
+                        let name = regex.replace(&label.name, self.replacement.as_str());
+                        (name.into_owned(), label.value.clone())
+                    })
+                    .collect();
+                for (name, value) in mapped {
+                    set_label(labels, &name, value);
+                }
+            }
+            RelabelAction::Hashmod => {
+                let hash = Md5::digest(value.as_bytes());
+                let hash = u64::from_be_bytes(hash[8..].try_into().expect("MD5 has 16 bytes"));
+                let modulus = self.modulus.unwrap_or(1).max(1);
+                set_label(labels, target_label, (hash % modulus).to_string());
+            }
+            RelabelAction::Lowercase => {
+                set_label(labels, target_label, value.to_lowercase());
+            }
+        }
+        true
+    }
+}
+
+/// Applies relabeling rules to a label set, in order.
+/// # Returns
+///     The relabeled labels, or `None` when a rule drops them.
+pub fn relabel(mut labels: Vec<Label>, configs: &[RelabelConfig]) -> Option<Vec<Label>> {
+    for config in configs {
+        if !config.apply(&mut labels) {
+            return None;
+        }
+    }
+    Some(labels)
+}
+
+/// Relabels the series of a target, like Prometheus does when scraping:
+///     * `relabel_configs` act on the labels of the target, such as `instance` and `job`, together with
+///       meta labels derived from its URL: `__url__`, `__scheme__`, `__host__`, `__port__` and
+///       `__path__`. The meta labels are removed afterwards. When the rules drop the target, all of its
+///       series are dropped.
+///     * `metric_relabel_configs` act on every series, including its `__name__` and `phase` labels.
+/// # Arguments
+///     * `metrics` - The series of the target, which are relabeled in place.
+///     * `url` - The URL of the target.
+///     * `relabel_configs` - The rules for the labels of the target.
+///     * `metric_relabel_configs` - The rules for every series.
+pub fn relabel_series(
+    metrics: &mut Vec<TimeSeries>,
+    url: &str,
+    relabel_configs: &[RelabelConfig],
+    metric_relabel_configs: &[RelabelConfig],
+) {
+    if relabel_configs.is_empty() && metric_relabel_configs.is_empty() {
+        return;
+    }
+    let meta_labels = meta_labels(url);
+
+    metrics.retain_mut(|series| {
+        let labels = std::mem::take(&mut series.labels);
+        let (series_labels, mut target_labels): (Vec<Label>, Vec<Label>) = labels
+            .into_iter()
+            .partition(|label| label.name == "__name__" || label.name == PHASE_LABEL);
+
+        if !relabel_configs.is_empty() {
+            target_labels.extend(meta_labels.iter().cloned());
+            let Some(relabeled) = relabel(target_labels, relabel_configs) else {
+                return false;
+            };
+            target_labels = relabeled;
+            target_labels.retain(|label| !label.name.starts_with("__"));
+        }
+        target_labels.retain(|target| !series_labels.iter().any(|l| l.name == target.name));
+
+        let mut labels = series_labels;
+        labels.extend(target_labels);
+        match relabel(labels, metric_relabel_configs) {
+            Some(labels) => {
+                series.labels = labels;
+                true
+            }
+            None => false,
+        }
+    });
+}
+
+/// Returns the meta labels of a target, derived from the components of its URL.
+fn meta_labels(url: &str) -> Vec<Label> {
+    let parsed = Url::parse(url).ok();
+    let component = |f: fn(&Url) -> Option<String>| parsed.as_ref().and_then(f);
+    [
+        ("__url__", Some(url.to_string())),
+        ("__scheme__", component(|u| Some(u.scheme().to_string()))),
+        ("__host__", component(|u| u.host_str().map(str::to_string))),
+        (
+            "__port__",
+            component(|u| u.port_or_known_default().map(|p| p.to_string())),
+        ),
+        ("__path__", component(|u| Some(u.path().to_string()))),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| {
+        value.map(|value| Label {
+            name: name.to_string(),
+            value,
+        })
+    })
+    .collect()
+}
+
+/// Returns the value of a label, or an empty string when the label is not set.
+fn label_value<'a>(labels: &'a [Label], name: &str) -> &'a str {
+    labels
+        .iter()
+        .find(|label| label.name == name)
+        .map(|label| label.value.as_str())
+        .unwrap_or_default()
+}
+
+/// Sets a label, removing it when the value is empty, like Prometheus.
+fn set_label(labels: &mut Vec<Label>, name: &str, value: String) {
+    labels.retain(|label| label.name != name);
+    if !value.is_empty() {
+        labels.push(Label {
+            name: name.to_string(),
+            value,
+        });
+    }
+}
+
+/// Whether a name is a valid Prometheus label name: letters, digits and underscores, not starting
+/// with a digit.
+pub fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mimir::client::create_time_series;
+
+    fn rules(yaml: &str) -> Vec<RelabelConfig> {
+        serde_yaml::from_str(yaml).expect("Invalid YAML")
+    }
+
+    fn labels(series: &TimeSeries) -> Vec<(&str, &str)> {
+        let mut labels: Vec<(&str, &str)> = series
+            .labels
+            .iter()
+            .map(|label| (label.name.as_str(), label.value.as_str()))
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    #[test]
+    fn test_relabel() {
+        let rules = rules(
+            r#"
+            - source_labels: [instance]
+              regex: https?://([^/]+).*
+              target_label: instance
+            - source_labels: [env, team]
+              target_label: owner
+            - source_labels: [team]
+              target_label: team
+              action: lowercase
+            - regex: te(.*)
+              replacement: owner_${1}
+              action: labelmap
+            - source_labels: [instance]
+              target_label: shard
+              modulus: 4
+              action: hashmod
+            - source_labels: [env]
+              regex: dev
+              action: drop
+            "#,
+        );
+        let input = vec![
+            Label {
+                name: "instance".to_string(),
+                value: "https://www.example.com/health".to_string(),
+            },
+            Label {
+                name: "env".to_string(),
+                value: "prod".to_string(),
+            },
+            Label {
+                name: "team".to_string(),
+                value: "Platform".to_string(),
+            },
+        ];
+
+        let relabeled = relabel(input.clone(), &rules).unwrap();
+        let series = TimeSeries {
+            labels: relabeled,
+            ..Default::default()
+        };
+        let shard = label_value(&series.labels, "shard").parse::<u64>().unwrap();
+        assert!(shard < 4);
+        assert_eq!(
+            labels(&series)
+                .into_iter()
+                .filter(|(name, _)| *name != "shard")
+                .collect::<Vec<_>>(),
+            vec![
+                ("env", "prod"),
+                ("instance", "www.example.com"),
+                ("owner", "prod;Platform"),
+                ("owner_am", "platform"),
+                ("team", "platform"),
+            ]
+        );
+
+        let mut dev = input;
+        dev[1].value = "dev".to_string();
+        assert!(relabel(dev, &rules).is_none());
+        assert!(relabel(vec![], &self::rules("- action: keep\n  regex: x")).is_none());
+    }
+
+    #[test]
+    fn test_relabel_series() {
+        let url = "https://www.example.com:8443/health";
+        let mut metrics = vec![
+            create_time_series("probe_success", &[("instance", url)], 1.0, Some(0)),
+            create_time_series(
+                "probe_http_duration_seconds",
+                &[("instance", url), ("phase", "resolve")],
+                0.1,
+                Some(0),
+            ),
+        ];
+        let relabel_configs = rules(
+            r#"
+            - source_labels: [__host__, __port__]
+              separator: ":"
+              target_label: instance
+            - source_labels: [__path__]
+              target_label: path
+            "#,
+        );
+        let metric_relabel_configs = rules(
+            r#"
+            - source_labels: [__name__, phase]
+              regex: probe_http_duration_seconds;resolve
+              action: drop
+            "#,
+        );
+
+        relabel_series(&mut metrics, url, &relabel_configs, &metric_relabel_configs);
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(
+            labels(&metrics[0]),
+            vec![
+                ("__name__", "probe_success"),
+                ("instance", "www.example.com:8443"),
+                ("path", "/health"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use chrono::Utc;
+
+use super::client::prometheus::prompb::{Label, Sample, TimeSeries};
+
+/// The special NaN value Prometheus uses to mark a series as stale.
+/// It differs from a regular NaN, so it can be told apart from a NaN sample value.
+pub const STALE_NAN: f64 = f64::from_bits(0x7ff0_0000_0000_0002);
+
+/// Returns `true` if the value is a Prometheus staleness marker.
+pub fn is_stale_marker(value: f64) -> bool {
+    value.to_bits() == STALE_NAN.to_bits()
+}
+
+/// Keeps track of the series pushed for every target of a tenant, so staleness markers can be
+/// emitted for series that disappear. Without these markers, Grafana keeps showing the last value
+/// of a series for five minutes after it stopped being written.
+#[derive(Default)]
+pub struct SeriesTracker {
+    series_by_target: Mutex<HashMap<String, HashSet<Vec<Label>>>>,
+}
+
+impl SeriesTracker {
+    /// Records the series pushed for a target and returns staleness markers for the series of
+    /// the previous push which are missing from this one, e.g. the phase durations of a failed probe.
+    /// # Arguments
+    ///     * `target` - The target the series belong to.
+    ///     * `metrics` - The series pushed for the target.
+    /// # Returns
+    ///     The staleness markers to push along with `metrics`.
+    pub fn record(&self, target: &str, metrics: &[TimeSeries]) -> Vec<TimeSeries> {
+        let current: HashSet<Vec<Label>> = metrics.iter().map(|m| m.labels.clone()).collect();
+        let mut series_by_target = self.series_by_target.lock().unwrap();
+        let previous = series_by_target
+            .insert(target.to_string(), current.clone())
+            .unwrap_or_default();
+
+        stale_markers(previous.difference(&current))
+    }
+
+    /// Forgets a target and returns staleness markers for all of its series.
+    pub fn remove_target(&self, target: &str) -> Vec<TimeSeries> {
+        let removed = self.series_by_target.lock().unwrap().remove(target);
+        removed.map(|s| stale_markers(s.iter())).unwrap_or_default()
+    }
+
+    /// Forgets all targets and returns staleness markers for every series pushed so far.
+    pub fn drain(&self) -> Vec<TimeSeries> {
+        let drained = std::mem::take(&mut *self.series_by_target.lock().unwrap());
+        stale_markers(drained.values().flatten())
+    }
+}
+
+/// Creates a staleness marker sample, timestamped now, for each of the given label sets.
+fn stale_markers<'a>(label_sets: impl Iterator<Item = &'a Vec<Label>>) -> Vec<TimeSeries> {
+    let timestamp = Utc::now().timestamp_millis();
+    label_sets
+        .map(|labels| TimeSeries {
+            labels: labels.clone(),
+            samples: vec![Sample {
+                value: STALE_NAN,
+                timestamp,
+            }],
+            exemplars: vec![],
+            histograms: vec![],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mimir::client::create_time_series;
+
+    #[test]
+    fn test_record_marks_missing_series_stale() {
+        let tracker = SeriesTracker::default();
+        let first = vec![
+            create_time_series("probe_success", &[("target", "a")], 1.0, None),
+            create_time_series(
+                "probe_http_duration_seconds",
+                &[("phase", "tls")],
+                0.1,
+                None,
+            ),
+        ];
+        assert!(tracker.record("a", &first).is_empty());
+
+        let second = vec![create_time_series(
+            "probe_success",
+            &[("target", "a")],
+            0.0,
+            None,
+        )];
+        let stale = tracker.record("a", &second);
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].labels, first[1].labels);
+        assert!(is_stale_marker(stale[0].samples[0].value));
+    }
+
+    #[test]
+    fn test_remove_target_and_drain() {
+        let tracker = SeriesTracker::default();
+        let a = vec![create_time_series(
+            "probe_success",
+            &[("target", "a")],
+            1.0,
+            None,
+        )];
+        let b = vec![create_time_series(
+            "probe_success",
+            &[("target", "b")],
+            1.0,
+            None,
+        )];
+        tracker.record("a", &a);
+        tracker.record("b", &b);
+
+        let removed = tracker.remove_target("a");
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].labels, a[0].labels);
+        assert!(tracker.remove_target("a").is_empty());
+
+        let drained = tracker.drain();
+        assert_eq!(drained.len(), 1);
+        assert_eq!(drained[0].labels, b[0].labels);
+        assert!(tracker.drain().is_empty());
+    }
+
+    #[test]
+    fn test_stale_marker_is_not_regular_nan() {
+        assert!(STALE_NAN.is_nan());
+        assert!(is_stale_marker(STALE_NAN));
+        assert!(!is_stale_marker(f64::NAN));
+    }
+}
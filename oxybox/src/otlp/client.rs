@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use reqwest::{
+    Client,
+    header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::interpolate::serialize_redacted_values;
+
+/// The transport used to export OTLP data to a collector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtlpProtocol {
+    /// OTLP/HTTP with binary protobuf payloads, usually on port 4318.
+    #[default]
+    #[serde(alias = "http/protobuf")]
+    Http,
+    /// OTLP/gRPC, usually on port 4317.
+    Grpc,
+}
+
+impl FromStr for OtlpProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "http" | "http/protobuf" => Ok(OtlpProtocol::Http),
+            "grpc" => Ok(OtlpProtocol::Grpc),
+            other => Err(format!(
+                "Unsupported OTLP protocol '{other}', expected 'http' or 'grpc'"
+            )),
+        }
+    }
+}
+
+/// An OpenTelemetry collector endpoint to export telemetry to.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct OtlpEndpoint {
+    /// The base URL of the collector (e.g., "http://localhost:4318").
+    #[serde(rename = "endpoint")]
+    pub url: String,
+
+    /// The transport used to export to the collector.
+    #[serde(default)]
+    pub protocol: OtlpProtocol,
+
+    /// Additional headers sent with every export, e.g. for authentication.
+    #[serde(default, serialize_with = "serialize_redacted_values")]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// An OTLP signal, with the paths its export requests are sent to.
+pub struct Signal {
+    /// The path of the OTLP/HTTP endpoint, relative to the base URL.
+    http_path: &'static str,
+    /// The full name of the gRPC export method.
+    grpc_method: &'static str,
+}
+
+/// The traces signal, exported to the `TraceService`.
+pub const TRACES: Signal = Signal {
+    http_path: "v1/traces",
+    grpc_method: "opentelemetry.proto.collector.trace.v1.TraceService/Export",
+};
+
+/// The metrics signal, exported to the `MetricsService`.
+pub const METRICS: Signal = Signal {
+    http_path: "v1/metrics",
+    grpc_method: "opentelemetry.proto.collector.metrics.v1.MetricsService/Export",
+};
+
+/// Exports a protobuf encoded OTLP export request to the collector.
+/// # Arguments
+///     * `endpoint` - The collector to export to.
+///     * `signal` - The signal the request belongs to, determining the path it is sent to.
+///     * `request` - The export request, e.g. an `ExportTraceServiceRequest` or `ExportMetricsServiceRequest`.
+/// # Errors
+///     Returns an error if the request cannot be sent or the collector rejects it.
+pub async fn export(
+    endpoint: &OtlpEndpoint,
+    signal: &Signal,
+    request: &impl prost::Message,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base_url = endpoint.url.trim_end_matches('/');
+    let mut headers = HeaderMap::new();
+    for (name, value) in &endpoint.headers {
+        headers.insert(HeaderName::from_str(name)?, HeaderValue::from_str(value)?);
+    }
+
+    let payload = request.encode_to_vec();
+    let response = match endpoint.protocol {
+        OtlpProtocol::Http => {
+            headers.insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static("application/x-protobuf"),
+            );
+            Client::new()
+                .post(format!("{base_url}/{}", signal.http_path))
+                .headers(headers)
+                .body(payload)
+                .send()
+                .await?
+        }
+        OtlpProtocol::Grpc => {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+            headers.insert("te", HeaderValue::from_static("trailers"));
+            Client::builder()
+                .http2_prior_knowledge()
+                .build()?
+                .post(format!("{base_url}/{}", signal.grpc_method))
+                .headers(headers)
+                .body(grpc_frame(&payload))
+                .send()
+                .await?
+        }
+    };
+
+    let status = response.status();
+    // gRPC errors without a response body are sent as headers, in a trailers-only response
+    let grpc_status = response
+        .headers()
+        .get("grpc-status")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("0")
+        .to_string();
+    if !status.is_success() || grpc_status != "0" {
+        let grpc_message = response
+            .headers()
+            .get("grpc-message")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!(
+            "Failed to export to {base_url}: {status} (grpc-status {grpc_status}) {grpc_message}{body}"
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Wraps a protobuf message in a gRPC length-prefixed frame: an uncompressed flag and a
+/// big-endian 32 bit length, followed by the message.
+fn grpc_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 5);
+    frame.push(0);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_otlp_protocol_from_str() {
+        assert_eq!("http".parse(), Ok(OtlpProtocol::Http));
+        assert_eq!("http/protobuf".parse(), Ok(OtlpProtocol::Http));
+        assert_eq!("GRPC".parse(), Ok(OtlpProtocol::Grpc));
+        assert!("http/json".parse::<OtlpProtocol>().is_err());
+    }
+
+    #[test]
+    fn test_otlp_endpoint_deserialization() {
+        let yaml = r#"
+            endpoint: http://otel-collector:4317
+            protocol: grpc
+            headers:
+                authorization: Bearer abc123
+        "#;
+
+        let endpoint: OtlpEndpoint = serde_yaml::from_str(yaml).expect("Invalid YAML");
+        assert_eq!(endpoint.url, "http://otel-collector:4317");
+        assert_eq!(endpoint.protocol, OtlpProtocol::Grpc);
+        assert_eq!(endpoint.headers["authorization"], "Bearer abc123");
+
+        let endpoint: OtlpEndpoint =
+            serde_yaml::from_str("endpoint: http://otel-collector:4318").expect("Invalid YAML");
+        assert_eq!(endpoint.protocol, OtlpProtocol::Http);
+        assert!(endpoint.headers.is_empty());
+    }
+
+    #[test]
+    fn test_grpc_frame() {
+        let frame = grpc_frame(&[1, 2, 3]);
+        assert_eq!(frame, vec![0, 0, 0, 0, 3, 1, 2, 3]);
+    }
+}
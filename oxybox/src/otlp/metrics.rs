@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+
+use crate::mimir::client::prometheus::prompb::{
+    self, BucketSpan, MetricMetadata, TimeSeries, histogram,
+};
+use crate::mimir::metadata::find_metadata;
+use crate::mimir::staleness::is_stale_marker;
+
+use super::opentelemetry::proto::collector::metrics::v1::ExportMetricsServiceRequest;
+use super::opentelemetry::proto::common::v1::KeyValue;
+use super::opentelemetry::proto::metrics::v1::{
+    AggregationTemporality, DataPointFlags, Exemplar, ExponentialHistogram,
+    ExponentialHistogramDataPoint, Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics,
+    exemplar, exponential_histogram_data_point::Buckets, metric, number_data_point,
+};
+use super::opentelemetry::proto::resource::v1::Resource;
+use super::{oxybox_scope, string_attribute};
+
+const NAME_LABEL: &str = "__name__";
+const JOB_LABEL: &str = "job";
+const INSTANCE_LABEL: &str = "instance";
+const TRACE_ID_LABEL: &str = "trace_id";
+const SPAN_ID_LABEL: &str = "span_id";
+const NANOS_PER_MILLI: u64 = 1_000_000;
+
+/// Converts the series built by `create_probe_metrics` into an OTLP export request.
+/// The series are grouped into a resource per `job` and `instance`, which become the `service.name`
+/// and `service.instance.id` resource attributes, so a collector exporting to Prometheus restores
+/// them as the `job` and `instance` labels. All other labels become data point attributes.
+/// Samples are converted to gauge data points, native histograms to exponential histograms and
+/// staleness markers to data points flagged as having no recorded value.
+/// # Arguments
+///     * `tenant` - The tenant name, added as `oxybox.tenant` resource attribute.
+///     * `org_id` - The organisation ID, added as `oxybox.organisation_id` resource attribute.
+///     * `metrics` - The series to convert.
+///     * `metadata` - The metadata of the metric families, for the description and unit of the metrics.
+/// # Returns
+///     An `ExportMetricsServiceRequest` to export to an OpenTelemetry collector.
+pub fn to_export_metrics_request(
+    tenant: &str,
+    org_id: &str,
+    metrics: &[TimeSeries],
+    metadata: &[MetricMetadata],
+) -> ExportMetricsServiceRequest {
+    let mut resources: BTreeMap<(String, String), BTreeMap<String, Metric>> = BTreeMap::new();
+
+    for series in metrics {
+        let mut name = String::new();
+        let mut job = String::new();
+        let mut instance = String::new();
+        let mut attributes = Vec::with_capacity(series.labels.len());
+        for label in &series.labels {
+            match label.name.as_str() {
+                NAME_LABEL => name = label.value.clone(),
+                JOB_LABEL => job = label.value.clone(),
+                INSTANCE_LABEL => instance = label.value.clone(),
+                _ => attributes.push(string_attribute(&label.name, &label.value)),
+            }
+        }
+
+        let metric = resources
+            .entry((job, instance))
+            .or_default()
+            .entry(name.clone())
+            .or_insert_with(|| {
+                let metadata = find_metadata(metadata, &name);
+                Metric {
+                    name: name.clone(),
+                    description: metadata.map(|m| m.help.clone()).unwrap_or_default(),
+                    unit: metadata.map(|m| to_ucum_unit(&m.unit)).unwrap_or_default(),
+                    data: None,
+                    metadata: vec![],
+                }
+            });
+
+        if !series.histograms.is_empty() {
+            let data_points = series
+                .histograms
+                .iter()
+                .map(|h| to_exponential_histogram_data_point(h, attributes.clone()));
+            match &mut metric.data {
+                Some(metric::Data::ExponentialHistogram(histogram)) => {
+                    histogram.data_points.extend(data_points)
+                }
+                _ => {
+                    metric.data = Some(metric::Data::ExponentialHistogram(ExponentialHistogram {
+                        data_points: data_points.collect(),
+                        aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                    }))
+                }
+            }
+            continue;
+        }
+
+        let exemplars: Vec<Exemplar> = series.exemplars.iter().map(to_exemplar).collect();
+        let data_points = series
+            .samples
+            .iter()
+            .map(|sample| to_number_data_point(sample, attributes.clone(), exemplars.clone()));
+        match &mut metric.data {
+            Some(metric::Data::Gauge(gauge)) => gauge.data_points.extend(data_points),
+            // a stale histogram series is sent as a sample, it stays an empty histogram
+            Some(_) => {}
+            None => {
+                metric.data = Some(metric::Data::Gauge(Gauge {
+                    data_points: data_points.collect(),
+                }))
+            }
+        }
+    }
+
+    ExportMetricsServiceRequest {
+        resource_metrics: resources
+            .into_iter()
+            .map(|((job, instance), metrics)| ResourceMetrics {
+                resource: Some(Resource {
+                    attributes: vec![
+                        string_attribute("service.name", &job),
+                        string_attribute("service.instance.id", &instance),
+                        string_attribute("oxybox.tenant", tenant),
+                        string_attribute("oxybox.organisation_id", org_id),
+                    ],
+                    dropped_attributes_count: 0,
+                }),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(oxybox_scope()),
+                    metrics: metrics.into_values().collect(),
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            })
+            .collect(),
+    }
+}
+
+/// Converts a Prometheus unit to its UCUM representation used by OpenTelemetry.
+fn to_ucum_unit(unit: &str) -> String {
+    match unit {
+        "seconds" => "s".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn to_unix_nanos(timestamp_ms: i64) -> u64 {
+    timestamp_ms.max(0) as u64 * NANOS_PER_MILLI
+}
+
+fn to_number_data_point(
+    sample: &prompb::Sample,
+    attributes: Vec<KeyValue>,
+    exemplars: Vec<Exemplar>,
+) -> NumberDataPoint {
+    let stale = is_stale_marker(sample.value);
+    NumberDataPoint {
+        attributes,
+        start_time_unix_nano: 0,
+        time_unix_nano: to_unix_nanos(sample.timestamp),
+        value: (!stale).then_some(number_data_point::Value::AsDouble(sample.value)),
+        exemplars,
+        flags: match stale {
+            true => DataPointFlags::NoRecordedValueMask as u32,
+            false => 0,
+        },
+    }
+}
+
+/// Converts a remote write exemplar, moving the `trace_id` and `span_id` labels to the dedicated
+/// fields of the OTLP exemplar.
+fn to_exemplar(exemplar: &prompb::Exemplar) -> Exemplar {
+    let mut converted = Exemplar {
+        time_unix_nano: to_unix_nanos(exemplar.timestamp),
+        value: Some(exemplar::Value::AsDouble(exemplar.value)),
+        ..Default::default()
+    };
+    for label in &exemplar.labels {
+        match (label.name.as_str(), from_hex(&label.value)) {
+            (TRACE_ID_LABEL, Some(trace_id)) => converted.trace_id = trace_id,
+            (SPAN_ID_LABEL, Some(span_id)) => converted.span_id = span_id,
+            _ => converted
+                .filtered_attributes
+                .push(string_attribute(&label.name, &label.value)),
+        }
+    }
+    converted
+}
+
+fn from_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn to_exponential_histogram_data_point(
+    histogram: &prompb::Histogram,
+    attributes: Vec<KeyValue>,
+) -> ExponentialHistogramDataPoint {
+    let count = match histogram.count {
+        Some(histogram::Count::CountInt(count)) => count,
+        Some(histogram::Count::CountFloat(count)) => count as u64,
+        None => 0,
+    };
+    let zero_count = match histogram.zero_count {
+        Some(histogram::ZeroCount::ZeroCountInt(count)) => count,
+        Some(histogram::ZeroCount::ZeroCountFloat(count)) => count as u64,
+        None => 0,
+    };
+    ExponentialHistogramDataPoint {
+        attributes,
+        time_unix_nano: to_unix_nanos(histogram.timestamp),
+        count,
+        sum: Some(histogram.sum),
+        scale: histogram.schema,
+        zero_count,
+        positive: Some(to_buckets(
+            &histogram.positive_spans,
+            &histogram.positive_deltas,
+        )),
+        negative: Some(to_buckets(
+            &histogram.negative_spans,
+            &histogram.negative_deltas,
+        )),
+        zero_threshold: histogram.zero_threshold,
+        ..Default::default()
+    }
+}
+
+/// Expands the sparse, delta encoded buckets of a native histogram into the dense bucket counts
+/// of an exponential histogram.
+/// Native histogram bucket `i` holds the values up to and including `base^i`, while exponential
+/// histogram bucket `i` holds the values above `base^i`, so the indexes are shifted by one.
+fn to_buckets(spans: &[BucketSpan], deltas: &[i64]) -> Buckets {
+    let mut deltas = deltas.iter();
+    let mut bucket_counts = Vec::with_capacity(deltas.len());
+    let mut offset = 0;
+    let mut count: i64 = 0;
+
+    for (i, span) in spans.iter().enumerate() {
+        match i {
+            0 => offset = span.offset - 1,
+            _ => bucket_counts.extend(std::iter::repeat_n(0, span.offset as usize)),
+        }
+        for delta in deltas.by_ref().take(span.length as usize) {
+            count += delta;
+            bucket_counts.push(count as u64);
+        }
+    }
+
+    Buckets {
+        offset,
+        bucket_counts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mimir::client::create_time_series;
+    use crate::mimir::metadata::probe_metadata;
+    use crate::mimir::staleness::STALE_NAN;
+
+    fn find_attribute<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a KeyValue> {
+        attributes.iter().find(|kv| kv.key == key)
+    }
+
+    #[test]
+    fn test_to_export_metrics_request() {
+        let labels = [
+            ("instance", "https://example.com"),
+            ("job", "oxybox"),
+            ("target", "https://example.com"),
+        ];
+        let mut duration = create_time_series("probe_duration_seconds", &labels, 0.2, Some(1_000));
+        duration.exemplars.push(prompb::Exemplar {
+            labels: vec![prompb::Label {
+                name: TRACE_ID_LABEL.to_string(),
+                value: "0af7651916cd43dd8448eb211c80319c".to_string(),
+            }],
+            value: 0.2,
+            timestamp: 1_000,
+        });
+        let metrics = vec![
+            create_time_series("probe_success", &labels, 1.0, Some(1_000)),
+            duration,
+            create_time_series("probe_http_status_code", &labels, STALE_NAN, Some(1_000)),
+        ];
+
+        let request = to_export_metrics_request("Tenant", "org-1", &metrics, &probe_metadata());
+        assert_eq!(request.resource_metrics.len(), 1);
+        let resource_metrics = &request.resource_metrics[0];
+        let resource = resource_metrics.resource.as_ref().unwrap();
+        assert!(find_attribute(&resource.attributes, "service.name").is_some());
+        assert!(find_attribute(&resource.attributes, "service.instance.id").is_some());
+
+        let metrics = &resource_metrics.scope_metrics[0].metrics;
+        assert_eq!(metrics.len(), 3);
+        let duration = metrics
+            .iter()
+            .find(|m| m.name == "probe_duration_seconds")
+            .unwrap();
+        assert_eq!(duration.unit, "s");
+        let Some(metric::Data::Gauge(gauge)) = &duration.data else {
+            panic!("Expected a gauge");
+        };
+        let point = &gauge.data_points[0];
+        assert_eq!(point.time_unix_nano, 1_000_000_000);
+        assert_eq!(point.value, Some(number_data_point::Value::AsDouble(0.2)));
+        assert!(find_attribute(&point.attributes, "target").is_some());
+        assert!(find_attribute(&point.attributes, "instance").is_none());
+        assert_eq!(point.exemplars[0].trace_id.len(), 16);
+
+        let status = metrics
+            .iter()
+            .find(|m| m.name == "probe_http_status_code")
+            .unwrap();
+        let Some(metric::Data::Gauge(gauge)) = &status.data else {
+            panic!("Expected a gauge");
+        };
+        assert_eq!(gauge.data_points[0].value, None);
+        assert_eq!(gauge.data_points[0].flags, 1);
+    }
+
+    #[test]
+    fn test_to_buckets() {
+        // native histogram buckets 1, 2 and 5 with counts 2, 3 and 1
+        let spans = [
+            BucketSpan {
+                offset: 1,
+                length: 2,
+            },
+            BucketSpan {
+                offset: 2,
+                length: 1,
+            },
+        ];
+        let buckets = to_buckets(&spans, &[2, 1, -2]);
+        assert_eq!(buckets.offset, 0);
+        assert_eq!(buckets.bucket_counts, vec![2, 3, 0, 0, 1]);
+    }
+}
@@ -0,0 +1,97 @@
+pub mod opentelemetry {
+    pub mod proto {
+        pub mod common {
+            pub mod v1 {
+                include!("../proto_generated/opentelemetry.proto.common.v1.rs");
+            }
+        }
+        pub mod resource {
+            pub mod v1 {
+                include!("../proto_generated/opentelemetry.proto.resource.v1.rs");
+            }
+        }
+        pub mod trace {
+            pub mod v1 {
+                include!("../proto_generated/opentelemetry.proto.trace.v1.rs");
+            }
+        }
+        pub mod metrics {
+            pub mod v1 {
+                include!("../proto_generated/opentelemetry.proto.metrics.v1.rs");
+            }
+        }
+        pub mod collector {
+            pub mod trace {
+                pub mod v1 {
+                    include!("../proto_generated/opentelemetry.proto.collector.trace.v1.rs");
+                }
+            }
+            pub mod metrics {
+                pub mod v1 {
+                    include!("../proto_generated/opentelemetry.proto.collector.metrics.v1.rs");
+                }
+            }
+        }
+    }
+}
+
+use opentelemetry::proto::common::v1::{AnyValue, InstrumentationScope, KeyValue, any_value};
+use opentelemetry::proto::resource::v1::Resource;
+
+pub mod client;
+pub mod metrics;
+pub mod trace;
+
+const SERVICE_NAME: &str = "oxybox";
+
+/// Creates an OTLP attribute with a string value.
+fn string_attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_string())),
+        }),
+    }
+}
+
+/// Creates an OTLP attribute with an integer value.
+fn int_attribute(key: &str, value: i64) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::IntValue(value)),
+        }),
+    }
+}
+
+/// Creates an OTLP attribute with a boolean value.
+fn bool_attribute(key: &str, value: bool) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::BoolValue(value)),
+        }),
+    }
+}
+
+/// The resource describing Oxybox as the producer of the exported telemetry.
+fn oxybox_resource(attributes: Vec<KeyValue>) -> Resource {
+    let mut all_attributes = vec![
+        string_attribute("service.name", SERVICE_NAME),
+        string_attribute("service.version", env!("CARGO_PKG_VERSION")),
+    ];
+    all_attributes.extend(attributes);
+    Resource {
+        attributes: all_attributes,
+        dropped_attributes_count: 0,
+    }
+}
+
+/// The instrumentation scope of the telemetry produced by Oxybox.
+fn oxybox_scope() -> InstrumentationScope {
+    InstrumentationScope {
+        name: SERVICE_NAME.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        ..Default::default()
+    }
+}
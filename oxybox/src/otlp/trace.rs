@@ -0,0 +1,200 @@
+use chrono::Utc;
+use url::Url;
+
+use crate::http_probe::result::ProbeResult;
+use crate::http_probe::trace::TraceContext;
+use crate::mimir::probe_phases;
+
+use super::opentelemetry::proto::collector::trace::v1::ExportTraceServiceRequest;
+use super::opentelemetry::proto::common::v1::KeyValue;
+use super::opentelemetry::proto::trace::v1::{
+    ResourceSpans, ScopeSpans, Span, Status, span::SpanKind, status::StatusCode,
+};
+use super::{bool_attribute, int_attribute, oxybox_resource, oxybox_scope, string_attribute};
+
+const NANOS_PER_MILLI: u64 = 1_000_000;
+const NANOS_PER_SECOND: f64 = 1_000_000_000.0;
+
+/// Creates an OTLP export request holding the trace of a single probe.
+/// The trace consists of a client span covering the whole probe, with the span ID injected into the
+/// probe request, and a child span for every phase of the probe that completed.
+/// # Arguments
+///     * `tenant` - The tenant name, added as attribute to the probe span.
+///     * `org_id` - The organisation ID, added as attribute to the probe span.
+///     * `probe_result` - The result of the probe, holding the trace context and phase durations.
+///     * `probe_success` - Whether the probe was successful, the span status is an error if not.
+///     * `error` - The error message of a failed probe, used as the status message of the span.
+/// # Returns
+///     An `ExportTraceServiceRequest` to export to an OpenTelemetry collector.
+pub fn create_probe_trace(
+    tenant: &str,
+    org_id: &str,
+    probe_result: &ProbeResult,
+    probe_success: bool,
+    error: Option<&str>,
+) -> ExportTraceServiceRequest {
+    let start_nanos = probe_result.started_at_ms.max(0) as u64 * NANOS_PER_MILLI;
+    // a probe which failed before completing has no total duration, so it ends when reported
+    let end_nanos = match error {
+        Some(_) => (Utc::now().timestamp_millis().max(0) as u64 * NANOS_PER_MILLI).max(start_nanos),
+        None => start_nanos + to_nanos(probe_result.total_probe_time),
+    };
+
+    let mut attributes = vec![
+        string_attribute("oxybox.tenant", tenant),
+        string_attribute("oxybox.organisation_id", org_id),
+        string_attribute("url.full", &probe_result.url),
+        string_attribute("http.request.method", "GET"),
+        bool_attribute("oxybox.probe.success", probe_success),
+    ];
+    if let Some(host) = Url::parse(&probe_result.url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+    {
+        attributes.push(string_attribute("server.address", &host));
+    }
+    if let Some(http_status) = probe_result.http_status {
+        attributes.push(int_attribute(
+            "http.response.status_code",
+            http_status as i64,
+        ));
+    }
+
+    let status = match (probe_success, error) {
+        (true, _) => Status::default(),
+        (false, Some(message)) => error_status(message),
+        (false, None) => error_status(&format!(
+            "Unexpected status code {}",
+            probe_result
+                .http_status
+                .map(|code| code.to_string())
+                .unwrap_or_else(|| "N/A".to_string())
+        )),
+    };
+
+    let mut spans = vec![create_span(
+        &probe_result.trace_context,
+        None,
+        "probe",
+        SpanKind::Client,
+        (start_nanos, end_nanos),
+        attributes,
+        Some(status),
+    )];
+
+    // the phases run one after the other, so each phase span starts where the previous one ended
+    let mut phase_start = start_nanos;
+    for (duration, phase) in probe_phases(probe_result) {
+        if let Some(duration) = duration {
+            let phase_end = phase_start + to_nanos(duration);
+            spans.push(create_span(
+                &probe_result.trace_context.child(),
+                Some(&probe_result.trace_context),
+                phase,
+                SpanKind::Internal,
+                (phase_start, phase_end),
+                vec![],
+                None,
+            ));
+            phase_start = phase_end;
+        }
+    }
+
+    ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: Some(oxybox_resource(vec![])),
+            scope_spans: vec![ScopeSpans {
+                scope: Some(oxybox_scope()),
+                spans,
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    }
+}
+
+/// Converts a duration in seconds to nanoseconds.
+fn to_nanos(seconds: f64) -> u64 {
+    (seconds.max(0.0) * NANOS_PER_SECOND).round() as u64
+}
+
+fn error_status(message: &str) -> Status {
+    Status {
+        message: message.to_string(),
+        code: StatusCode::Error as i32,
+    }
+}
+
+fn create_span(
+    context: &TraceContext,
+    parent: Option<&TraceContext>,
+    name: &str,
+    kind: SpanKind,
+    (start_nanos, end_nanos): (u64, u64),
+    attributes: Vec<KeyValue>,
+    status: Option<Status>,
+) -> Span {
+    Span {
+        trace_id: context.trace_id.to_vec(),
+        span_id: context.span_id.to_vec(),
+        parent_span_id: parent
+            .map(|parent| parent.span_id.to_vec())
+            .unwrap_or_default(),
+        name: name.to_string(),
+        kind: kind as i32,
+        start_time_unix_nano: start_nanos,
+        end_time_unix_nano: end_nanos,
+        attributes,
+        status,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe_result() -> ProbeResult {
+        ProbeResult {
+            url: "https://example.com/health".to_string(),
+            started_at_ms: 1_000,
+            dns_time: Some(0.01),
+            connect_time: Some(0.02),
+            tls_time: None,
+            http_status: Some(503),
+            http_version: Some(1.1),
+            cert_validity_seconds: None,
+            processing_time: Some(0.1),
+            transfer_time: Some(0.05),
+            total_probe_time: 0.2,
+            trace_context: TraceContext::new(),
+        }
+    }
+
+    #[test]
+    fn test_create_probe_trace() {
+        let probe = probe_result();
+        let request = create_probe_trace("Tenant", "org-1", &probe, false, None);
+        let spans = &request.resource_spans[0].scope_spans[0].spans;
+
+        // the probe span and the four phases which completed
+        assert_eq!(spans.len(), 5);
+        let root = &spans[0];
+        assert_eq!(root.span_id, probe.trace_context.span_id.to_vec());
+        assert!(root.parent_span_id.is_empty());
+        assert_eq!(root.start_time_unix_nano, 1_000_000_000);
+        assert_eq!(root.end_time_unix_nano, 1_200_000_000);
+        assert_eq!(root.status.as_ref().unwrap().code, StatusCode::Error as i32);
+        assert!(root.attributes.iter().any(|kv| kv.key == "server.address"));
+
+        let names: Vec<&str> = spans[1..].iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["resolve", "connect", "processing", "transfer"]);
+        for window in spans[1..].windows(2) {
+            assert_eq!(window[0].end_time_unix_nano, window[1].start_time_unix_nano);
+        }
+        for span in &spans[1..] {
+            assert_eq!(span.trace_id, root.trace_id);
+            assert_eq!(span.parent_span_id, root.span_id);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+/// The number of cycles a probe loop may go without starting the next before it is considered stuck.
+/// A cycle takes at most the wait for its probe and the probe itself, plus the time to write its metrics.
+const LOOP_STALE_INTERVALS: u32 = 3;
+
+/// The last cycle of the probe loop of a target.
+struct LoopHeartbeat {
+    started_at: Instant,
+    cycle: Duration,
+}
+
+/// The writes of a tenant to its sinks.
+#[derive(Default)]
+struct TenantWrites {
+    last_success: Option<Instant>,
+    /// The first write which failed since the last successful one.
+    failing_since: Option<Instant>,
+}
+
+/// Tracks whether Oxybox is ready to do its work, for the `/readyz` endpoint.
+#[derive(Default)]
+pub struct Health {
+    config_loaded: AtomicBool,
+    shutting_down: AtomicBool,
+    /// The heartbeats of the probe loops, keyed by tenant and target URL.
+    loops: Mutex<HashMap<(String, String), LoopHeartbeat>>,
+    writes: Mutex<HashMap<String, TenantWrites>>,
+}
+
+/// The health shared by all probe loops, sinks and the HTTP server.
+pub static HEALTH: LazyLock<Health> = LazyLock::new(Health::default);
+
+impl Health {
+    /// Marks the probe configuration as loaded and the probe loops as started.
+    pub fn mark_config_loaded(&self) {
+        self.config_loaded.store(true, Ordering::Relaxed);
+    }
+
+    /// Marks Oxybox as shutting down, so it is taken out of rotation while it drains its probes.
+    pub fn mark_shutting_down(&self) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+    }
+
+    /// Records the start of a cycle of the probe loop of a target.
+    /// # Arguments
+    ///     * `tenant` - The tenant name.
+    ///     * `target` - The URL of the target.
+    ///     * `cycle` - How long the cycle may take: the wait for the next probe plus its timeout.
+    pub fn record_loop_cycle(&self, tenant: &str, target: &str, cycle: Duration) {
+        self.loops.lock().unwrap().insert(
+            (tenant.to_string(), target.to_string()),
+            LoopHeartbeat {
+                started_at: Instant::now(),
+                cycle,
+            },
+        );
+    }
+
+    /// Forgets the probe loop of a target, when it stops on purpose.
+    pub fn remove_loop(&self, tenant: &str, target: &str) {
+        self.loops
+            .lock()
+            .unwrap()
+            .remove(&(tenant.to_string(), target.to_string()));
+    }
+
+    /// Forgets the probe loops and writes of a tenant, when it is stopped on purpose.
+    pub fn remove_tenant(&self, tenant: &str) {
+        self.loops
+            .lock()
+            .unwrap()
+            .retain(|(name, _), _| name != tenant);
+        self.writes.lock().unwrap().remove(tenant);
+    }
+
+    /// Records a write of a batch of a tenant, which succeeded when it reached all of its sinks.
+    pub fn record_write(&self, tenant: &str, success: bool) {
+        let now = Instant::now();
+        let mut writes = self.writes.lock().unwrap();
+        let writes = writes.entry(tenant.to_string()).or_default();
+        match success {
+            true => {
+                writes.last_success = Some(now);
+                writes.failing_since = None;
+            }
+            false => {
+                writes.failing_since.get_or_insert(now);
+            }
+        }
+    }
+
+    /// Checks whether Oxybox is ready: the configuration is loaded, Oxybox is not shutting down, the
+    /// probe loop of every target started a cycle recently, and no tenant failed to write its metrics for longer than
+    /// `max_write_age`.
+    /// # Arguments
+    ///     * `max_write_age` - How long the writes of a tenant may fail.
+    ///     * `now` - The moment to check the readiness at.
+    /// # Returns
+    ///     `Ok` when ready, or the reasons why Oxybox is not ready.
+    pub fn readiness(&self, max_write_age: Duration, now: Instant) -> Result<(), Vec<String>> {
+        let mut reasons = Vec::new();
+        if !self.config_loaded.load(Ordering::Relaxed) {
+            reasons.push("The probe configuration is not loaded yet".to_string());
+        }
+        if self.shutting_down.load(Ordering::Relaxed) {
+            reasons.push("Oxybox is shutting down".to_string());
+        }
+
+        let loops = self.loops.lock().unwrap();
+        let mut keys: Vec<_> = loops.keys().collect();
+        keys.sort();
+        for key @ (tenant, target) in keys {
+            let heartbeat = &loops[key];
+            let since = now.saturating_duration_since(heartbeat.started_at);
+            if since > heartbeat.cycle * LOOP_STALE_INTERVALS {
+                reasons.push(format!(
+                    "The probe loop of {target} of {tenant} did not run for {}s",
+                    since.as_secs()
+                ));
+            }
+        }
+
+        let writes = self.writes.lock().unwrap();
+        let mut tenants: Vec<_> = writes.keys().collect();
+        tenants.sort();
+        for tenant in tenants {
+            let Some(failing_since) = writes[tenant].failing_since else {
+                continue;
+            };
+            // a tenant which never wrote successfully is failing since its first write
+            let since =
+                now.saturating_duration_since(writes[tenant].last_success.unwrap_or(failing_since));
+            if now.saturating_duration_since(failing_since) > max_write_age {
+                reasons.push(format!(
+                    "No metrics of {tenant} were written successfully for {}s",
+                    since.as_secs()
+                ));
+            }
+        }
+
+        match reasons.is_empty() {
+            true => Ok(()),
+            false => Err(reasons),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness() {
+        let health = Health::default();
+        let max_write_age = Duration::from_secs(300);
+        assert_eq!(
+            health
+                .readiness(max_write_age, Instant::now())
+                .unwrap_err()
+                .len(),
+            1
+        );
+
+        health.mark_config_loaded();
+        health.record_loop_cycle("acme", "https://a.example.com", Duration::from_secs(10));
+        health.record_loop_cycle("acme", "https://b.example.com", Duration::from_secs(10));
+        health.record_write("acme", true);
+        assert!(health.readiness(max_write_age, Instant::now()).is_ok());
+
+        // every target loop is checked on its own
+        let later = Instant::now() + Duration::from_secs(60);
+        health.record_loop_cycle("acme", "https://b.example.com", Duration::from_secs(60));
+        let reasons = health.readiness(max_write_age, later).unwrap_err();
+        assert_eq!(reasons.len(), 1);
+        assert!(
+            reasons[0].starts_with("The probe loop of https://a.example.com of acme did not run")
+        );
+
+        health.remove_loop("acme", "https://a.example.com");
+        assert!(health.readiness(max_write_age, later).is_ok());
+        health.remove_tenant("acme");
+        assert!(health.readiness(max_write_age, later).is_ok());
+
+        // a tenant whose writes keep failing is reported, even when other tenants write fine
+        health.record_write("acme", true);
+        health.record_write("other", false);
+        assert!(health.readiness(max_write_age, later).is_ok());
+        let reasons = health
+            .readiness(max_write_age, later + max_write_age)
+            .unwrap_err();
+        assert_eq!(reasons.len(), 1);
+        assert!(reasons[0].starts_with("No metrics of other were written successfully"));
+        health.record_write("other", true);
+        assert!(health.readiness(max_write_age, later).is_ok());
+        health.mark_shutting_down();
+        assert_eq!(
+            health.readiness(max_write_age, later),
+            Err(vec!["Oxybox is shutting down".to_string()])
+        );
+        assert!(
+            health
+                .readiness(max_write_age, later + max_write_age)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::mimir::client::prometheus::prompb::{
+    MetricMetadata, TimeSeries, metric_metadata::MetricType,
+};
+use crate::mimir::client::{
+    ExternalLabels, MimirEndpoint, RemoteWrite, create_time_series, send_to_mimir,
+};
+
+pub mod health;
+
+const PROBES_METRIC: &str = "oxybox_probes_total";
+const WRITE_FAILURES_METRIC: &str = "oxybox_sink_write_failures_total";
+const REMOTE_WRITE_FAILURES_METRIC: &str = "oxybox_remote_write_failures_total";
+const REMOTE_WRITE_RETRIES_METRIC: &str = "oxybox_remote_write_retries_total";
+const TASK_PANICS_METRIC: &str = "oxybox_task_panics_total";
+const SCHEDULE_LAG_METRIC: &str = "oxybox_probe_schedule_lag_seconds";
+const TARGETS_METRIC: &str = "oxybox_targets";
+const BUILD_INFO_METRIC: &str = "oxybox_build_info";
+const CONFIG_RELOADS_METRIC: &str = "oxybox_config_reloads_total";
+const CONFIG_LAST_RELOAD_SUCCESSFUL_METRIC: &str = "oxybox_config_last_reload_successful";
+const TENANT_LABEL: &str = "tenant";
+const TARGET_LABEL: &str = "target";
+const RESULT_LABEL: &str = "result";
+const SINK_LABEL: &str = "sink";
+const VERSION_LABEL: &str = "version";
+
+/// The metadata of every metric Oxybox exposes about itself, in the layout of the probe metric registry.
+const SELF_METRIC_METADATA: &[(&str, MetricType, &str, &str)] = &[
+    (
+        PROBES_METRIC,
+        MetricType::Counter,
+        "Total number of probes run, by result",
+        "",
+    ),
+    (
+        WRITE_FAILURES_METRIC,
+        MetricType::Counter,
+        "Total number of writes of probe metrics which failed, by sink",
+        "",
+    ),
+    (
+        REMOTE_WRITE_FAILURES_METRIC,
+        MetricType::Counter,
+        "Total number of remote writes to Mimir which failed, after retrying",
+        "",
+    ),
+    (
+        REMOTE_WRITE_RETRIES_METRIC,
+        MetricType::Counter,
+        "Total number of remote writes to Mimir which were retried",
+        "",
+    ),
+    (
+        TASK_PANICS_METRIC,
+        MetricType::Counter,
+        "Total number of probe tasks which panicked",
+        "",
+    ),
+    (
+        SCHEDULE_LAG_METRIC,
+        MetricType::Gauge,
+        "How late the last probe of a target started compared to its schedule",
+        "seconds",
+    ),
+    (
+        TARGETS_METRIC,
+        MetricType::Gauge,
+        "Number of targets probed by a tenant",
+        "",
+    ),
+    (
+        CONFIG_RELOADS_METRIC,
+        MetricType::Counter,
+        "Total number of reloads of the probe configuration, by result",
+        "",
+    ),
+    (
+        CONFIG_LAST_RELOAD_SUCCESSFUL_METRIC,
+        MetricType::Gauge,
+        "Whether the last reload of the probe configuration succeeded",
+        "",
+    ),
+    (
+        BUILD_INFO_METRIC,
+        MetricType::Gauge,
+        "A metric with a constant '1' value labeled by the version of Oxybox",
+        "",
+    ),
+];
+
+/// The outcome of a single probe, as counted by `oxybox_probes_total`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeOutcome {
+    /// The probe completed with an accepted status code.
+    Success,
+    /// The probe failed, or completed with an unexpected status code.
+    Failure,
+    /// The probe did not complete within the polling interval.
+    Timeout,
+}
+
+impl ProbeOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ProbeOutcome::Success => "success",
+            ProbeOutcome::Failure => "failure",
+            ProbeOutcome::Timeout => "timeout",
+        }
+    }
+}
+
+/// A series in the registry, identified by its metric name and labels.
+type SeriesKey = (&'static str, Vec<(&'static str, String)>);
+
+/// The registry of the metrics Oxybox keeps about itself, keyed by metric name and labels.
+#[derive(Default)]
+pub struct SelfMetrics {
+    /// The value of every series, with the unix timestamp in milliseconds a counter was created at,
+    /// or 0 for a gauge.
+    values: Mutex<BTreeMap<SeriesKey, (f64, i64)>>,
+}
+
+/// The registry shared by all probe loops, sinks and the HTTP server.
+pub static SELF_METRICS: LazyLock<SelfMetrics> = LazyLock::new(SelfMetrics::default);
+
+impl SelfMetrics {
+    fn add(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        self.values
+            .lock()
+            .unwrap()
+            .entry((name, to_owned_labels(labels)))
+            .or_insert_with(|| (0.0, Utc::now().timestamp_millis()))
+            .0 += value;
+    }
+
+    fn set(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        self.values
+            .lock()
+            .unwrap()
+            .insert((name, to_owned_labels(labels)), (value, 0));
+    }
+
+    /// Counts a probe of a tenant.
+    pub fn record_probe(&self, tenant: &str, outcome: ProbeOutcome) {
+        self.add(
+            PROBES_METRIC,
+            &[(TENANT_LABEL, tenant), (RESULT_LABEL, outcome.as_str())],
+            1.0,
+        );
+    }
+
+    /// Counts a write of a tenant which failed to reach a sink.
+    pub fn record_write_failure(&self, tenant: &str, sink: &str) {
+        self.add(
+            WRITE_FAILURES_METRIC,
+            &[(TENANT_LABEL, tenant), (SINK_LABEL, sink)],
+            1.0,
+        );
+    }
+
+    /// Counts a remote write of a tenant to Mimir which failed, after retrying.
+    pub fn record_remote_write_failure(&self, tenant: &str) {
+        self.add(REMOTE_WRITE_FAILURES_METRIC, &[(TENANT_LABEL, tenant)], 1.0);
+    }
+
+    /// Counts a retry of a remote write of a tenant.
+    pub fn record_remote_write_retry(&self, tenant: &str) {
+        self.add(REMOTE_WRITE_RETRIES_METRIC, &[(TENANT_LABEL, tenant)], 1.0);
+    }
+
+    /// Counts a probe task of a tenant which panicked.
+    pub fn record_task_panic(&self, tenant: &str) {
+        self.add(TASK_PANICS_METRIC, &[(TENANT_LABEL, tenant)], 1.0);
+    }
+
+    /// Sets how late the last probe of a target of a tenant started.
+    pub fn set_schedule_lag(&self, tenant: &str, target: &str, lag: Duration) {
+        self.set(
+            SCHEDULE_LAG_METRIC,
+            &[(TENANT_LABEL, tenant), (TARGET_LABEL, target)],
+            lag.as_secs_f64(),
+        );
+    }
+
+    /// Removes the schedule lag of a target which is no longer probed.
+    pub fn remove_target(&self, tenant: &str, target: &str) {
+        self.values.lock().unwrap().remove(&(
+            SCHEDULE_LAG_METRIC,
+            to_owned_labels(&[(TENANT_LABEL, tenant), (TARGET_LABEL, target)]),
+        ));
+    }
+
+    /// Sets the number of targets probed by a tenant.
+    pub fn set_targets(&self, tenant: &str, targets: usize) {
+        self.set(TARGETS_METRIC, &[(TENANT_LABEL, tenant)], targets as f64);
+    }
+
+    /// Removes the gauges of a tenant which is no longer probed. Its counters are kept, so they do
+    /// not reset when the tenant is added again.
+    pub fn remove_tenant(&self, tenant: &str) {
+        self.values.lock().unwrap().retain(|(name, labels), _| {
+            ![SCHEDULE_LAG_METRIC, TARGETS_METRIC].contains(name)
+                || !labels.contains(&(TENANT_LABEL, tenant.to_string()))
+        });
+    }
+
+    /// Counts a reload of the probe configuration.
+    pub fn record_config_reload(&self, success: bool) {
+        let result = match success {
+            true => "success",
+            false => "failure",
+        };
+        self.add(CONFIG_RELOADS_METRIC, &[(RESULT_LABEL, result)], 1.0);
+        self.set(
+            CONFIG_LAST_RELOAD_SUCCESSFUL_METRIC,
+            &[],
+            f64::from(u8::from(success)),
+        );
+    }
+
+    /// Returns a series for every metric in the registry, including the build info.
+    /// # Arguments
+    ///     * `timestamp_ms` - The timestamp in unix milliseconds of every sample.
+    pub fn series(&self, timestamp_ms: i64) -> Vec<TimeSeries> {
+        self.series_with_created_timestamps(timestamp_ms).0
+    }
+
+    /// Returns a series for every metric in the registry, see `series`.
+    /// # Returns
+    ///     The series, and the created timestamp of every series: when a counter was created, or 0
+    ///     for a gauge.
+    pub fn series_with_created_timestamps(&self, timestamp_ms: i64) -> (Vec<TimeSeries>, Vec<i64>) {
+        let mut series = vec![create_time_series(
+            BUILD_INFO_METRIC,
+            &[(VERSION_LABEL, env!("CARGO_PKG_VERSION"))],
+            1.0,
+            Some(timestamp_ms),
+        )];
+        let mut created_timestamps = vec![0];
+        for ((name, labels), (value, created_ms)) in self.values.lock().unwrap().iter() {
+            let labels: Vec<(&str, &str)> = labels
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect();
+            series.push(create_time_series(
+                name,
+                &labels,
+                *value,
+                Some(timestamp_ms),
+            ));
+            created_timestamps.push(*created_ms);
+        }
+        (series, created_timestamps)
+    }
+}
+
+fn to_owned_labels(labels: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+    labels
+        .iter()
+        .map(|(name, value)| (*name, value.to_string()))
+        .collect()
+}
+
+/// Returns the metadata (HELP, TYPE and UNIT) of every metric Oxybox exposes about itself.
+pub fn self_metadata() -> Vec<MetricMetadata> {
+    SELF_METRIC_METADATA
+        .iter()
+        .map(|(name, metric_type, help, unit)| MetricMetadata {
+            r#type: *metric_type as i32,
+            metric_family_name: name.to_string(),
+            help: help.to_string(),
+            unit: unit.to_string(),
+        })
+        .collect()
+}
+
+/// Pushes the metrics Oxybox keeps about itself to the admin tenant in Mimir, once every interval.
+/// # Arguments
+///     * `mimir_endpoint` - The Mimir endpoint to push to.
+///     * `external_labels` - The labels added to every series.
+///     * `admin_tenant` - The organisation ID the metrics are pushed as.
+///     * `interval` - How often the metrics are pushed.
+pub async fn run_push_loop(
+    mimir_endpoint: MimirEndpoint,
+    external_labels: ExternalLabels,
+    admin_tenant: String,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let (mut series, created_timestamps) =
+            SELF_METRICS.series_with_created_timestamps(Utc::now().timestamp_millis());
+        external_labels.apply(&mut series);
+        let write = RemoteWrite {
+            metrics: series,
+            metadata: self_metadata(),
+            created_timestamps,
+            version: mimir_endpoint.remote_write_version,
+        };
+        if let Err(e) = send_to_mimir(&mimir_endpoint.url, Some(&admin_tenant), write).await {
+            log::error!("Failed to push the Oxybox metrics to {admin_tenant}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_metrics() {
+        let metrics = SelfMetrics::default();
+        metrics.record_probe("acme", ProbeOutcome::Success);
+        metrics.record_probe("acme", ProbeOutcome::Success);
+        metrics.record_probe("acme", ProbeOutcome::Timeout);
+        metrics.record_write_failure("acme", "mimir");
+        metrics.record_remote_write_failure("acme");
+        metrics.record_remote_write_retry("acme");
+        metrics.set_targets("acme", 3);
+        metrics.set_targets("acme", 2);
+
+        let series = metrics.series(1_000);
+        let value = |name: &str, label: (&str, &str)| {
+            series
+                .iter()
+                .find(|series| {
+                    series.labels.iter().any(|l| l.value == name)
+                        && series
+                            .labels
+                            .iter()
+                            .any(|l| l.name == label.0 && l.value == label.1)
+                })
+                .map(|series| series.samples[0].value)
+        };
+
+        assert_eq!(value(PROBES_METRIC, (RESULT_LABEL, "success")), Some(2.0));
+        assert_eq!(value(PROBES_METRIC, (RESULT_LABEL, "timeout")), Some(1.0));
+        assert_eq!(value(PROBES_METRIC, (RESULT_LABEL, "failure")), None);
+        assert_eq!(
+            value(WRITE_FAILURES_METRIC, (SINK_LABEL, "mimir")),
+            Some(1.0)
+        );
+        assert_eq!(
+            value(REMOTE_WRITE_FAILURES_METRIC, (TENANT_LABEL, "acme")),
+            Some(1.0)
+        );
+        assert_eq!(
+            value(REMOTE_WRITE_RETRIES_METRIC, (TENANT_LABEL, "acme")),
+            Some(1.0)
+        );
+        assert_eq!(value(TARGETS_METRIC, (TENANT_LABEL, "acme")), Some(2.0));
+        assert_eq!(
+            value(
+                BUILD_INFO_METRIC,
+                (VERSION_LABEL, env!("CARGO_PKG_VERSION"))
+            ),
+            Some(1.0)
+        );
+
+        // counters carry when they were created, gauges do not
+        let (series, created_timestamps) = metrics.series_with_created_timestamps(1_000);
+        assert_eq!(series.len(), created_timestamps.len());
+        for (series, created_ms) in series.iter().zip(created_timestamps) {
+            let name = &series.labels[0].value;
+            assert_eq!(created_ms > 0, name.ends_with("_total"), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_remove_tenant() {
+        let metrics = SelfMetrics::default();
+        metrics.record_probe("acme", ProbeOutcome::Failure);
+        metrics.set_targets("acme", 1);
+        metrics.set_targets("other", 1);
+        metrics.set_schedule_lag("acme", "https://a.example.com", Duration::from_millis(5));
+        metrics.set_schedule_lag("other", "https://a.example.com", Duration::from_millis(5));
+        metrics.set_schedule_lag("other", "https://b.example.com", Duration::from_millis(5));
+        metrics.record_config_reload(false);
+        metrics.remove_tenant("acme");
+        metrics.remove_target("other", "https://b.example.com");
+
+        let names: Vec<String> = metrics
+            .series(1_000)
+            .iter()
+            .map(|series| {
+                series
+                    .labels
+                    .iter()
+                    .map(|l| format!("{}={}", l.name, l.value))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect();
+        assert!(names.iter().any(|n| n.contains(PROBES_METRIC)));
+        assert!(names.contains(&format!("__name__={TARGETS_METRIC},tenant=other")));
+        assert!(!names.contains(&format!("__name__={TARGETS_METRIC},tenant=acme")));
+        let lags: Vec<&String> = names
+            .iter()
+            .filter(|n| n.contains(SCHEDULE_LAG_METRIC))
+            .collect();
+        assert_eq!(
+            lags,
+            vec![&format!(
+                "__name__={SCHEDULE_LAG_METRIC},tenant=other,target=https://a.example.com"
+            )]
+        );
+        assert!(names.contains(&format!("__name__={CONFIG_RELOADS_METRIC},result=failure")));
+        assert!(names.contains(&format!("__name__={CONFIG_LAST_RELOAD_SUCCESSFUL_METRIC}")));
+    }
+}
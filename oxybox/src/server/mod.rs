@@ -0,0 +1,193 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{ACCEPT, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+
+use crate::exposition::LatestSeries;
+use crate::exposition::format::{ExpositionFormat, render};
+use crate::http_probe::probe::ProbeContext;
+use crate::mimir::client::prometheus::prompb::{MetricMetadata, TimeSeries};
+use crate::self_monitoring::health::HEALTH;
+use crate::self_monitoring::{SELF_METRICS, self_metadata};
+
+pub mod probe;
+
+/// The state shared by all requests to the HTTP server.
+pub struct ServerState {
+    /// The latest series of every tenant, exposed on `/metrics`.
+    pub latest: Arc<LatestSeries>,
+
+    /// The resolver and TLS connector used by `/probe`.
+    pub probe_context: ProbeContext,
+
+    /// How long ago the last successful write may be for `/readyz` to report ready.
+    pub readiness_max_write_age: Duration,
+}
+
+/// Runs the HTTP server, serving:
+///     * `/metrics` - The latest series of all tenants, or of one tenant with `?tenant=<tenant>`.
+///     * `/metrics/<tenant>` - The latest series of one tenant.
+///     * `/probe?target=<url>&module=<module>` - Probes a target on demand, like the Blackbox Exporter.
+///     * `/admin/metrics` - The metrics Oxybox keeps about itself.
+///     * `/healthz` - Whether the process is alive, for liveness probes.
+///     * `/readyz` - Whether the configuration is loaded, every probe loop is running and metrics were
+///       written recently, for readiness probes.
+///
+/// The tenant is matched against both the tenant name and the organisation ID.
+/// # Arguments
+///     * `listen_address` - The address to listen on (e.g., 0.0.0.0:9115).
+///     * `state` - The state shared by all requests.
+/// # Errors
+///     Returns an error if the server cannot listen on the address.
+pub async fn serve(listen_address: SocketAddr, state: Arc<ServerState>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(listen_address).await?;
+    log::info!("Listening on http://{listen_address}");
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                log::error!("Failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| handle(request, state.clone()));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                log::debug!("Failed to serve connection: {e}");
+            }
+        });
+    }
+}
+
+async fn handle(
+    request: Request<Incoming>,
+    state: Arc<ServerState>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    if request.method() != Method::GET {
+        return Ok(text_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Method not allowed\n",
+        ));
+    }
+
+    let path = request.uri().path().trim_end_matches('/');
+    if path == "/probe" {
+        return Ok(probe::probe(&request, &state.probe_context).await);
+    }
+    if path == "/healthz" {
+        return Ok(text_response(StatusCode::OK, "OK\n"));
+    }
+    if path == "/readyz" {
+        return Ok(
+            match HEALTH.readiness(state.readiness_max_write_age, Instant::now()) {
+                Ok(()) => text_response(StatusCode::OK, "OK\n"),
+                Err(reasons) => text_response(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    &format!("{}\n", reasons.join("\n")),
+                ),
+            },
+        );
+    }
+    if path == "/admin/metrics" {
+        let series = SELF_METRICS.series(Utc::now().timestamp_millis());
+        return Ok(exposition(&request, &series, &self_metadata()));
+    }
+    let response = match path.split_once("/metrics") {
+        Some(("", "")) => metrics(&request, &state, query_param(&request, "tenant").as_deref()),
+        Some(("", tenant)) if tenant.starts_with('/') => {
+            metrics(&request, &state, Some(&percent_decode(&tenant[1..])))
+        }
+        _ => text_response(StatusCode::NOT_FOUND, "Not found\n"),
+    };
+    Ok(response)
+}
+
+/// Serves the latest series of one or all tenants, in the format requested by the scraper.
+fn metrics(
+    request: &Request<Incoming>,
+    state: &ServerState,
+    tenant: Option<&str>,
+) -> Response<Full<Bytes>> {
+    let Some((series, metadata)) = state.latest.snapshot(tenant) else {
+        return text_response(StatusCode::NOT_FOUND, "Unknown tenant\n");
+    };
+    exposition(request, &series, &metadata)
+}
+
+/// Renders series in the format requested by the scraper.
+fn exposition(
+    request: &Request<Incoming>,
+    series: &[TimeSeries],
+    metadata: &[MetricMetadata],
+) -> Response<Full<Bytes>> {
+    let accept = request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    let format = ExpositionFormat::from_accept(accept);
+    response(
+        StatusCode::OK,
+        format.content_type(),
+        render(series, format, metadata),
+    )
+}
+
+/// Returns the decoded value of a query parameter of the request.
+pub fn query_param(request: &Request<Incoming>, name: &str) -> Option<String> {
+    url::form_urlencoded::parse(request.uri().query()?.as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Decodes a percent-encoded path segment. Unlike in a query, a `+` in a path is not a space.
+fn percent_decode(value: &str) -> String {
+    percent_encoding::percent_decode_str(value)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Creates a response with the given body and content type.
+pub fn response(
+    status: StatusCode,
+    content_type: &'static str,
+    body: String,
+) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, content_type.parse().unwrap());
+    response
+}
+
+/// Creates a plain text response.
+pub fn text_response(status: StatusCode, body: &str) -> Response<Full<Bytes>> {
+    response(status, "text/plain; charset=utf-8", body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("acme"), "acme");
+        assert_eq!(percent_decode("acme%20corp"), "acme corp");
+        assert_eq!(percent_decode("a+b%2Bc"), "a+b+c");
+    }
+}
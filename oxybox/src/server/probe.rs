@@ -0,0 +1,187 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::ACCEPT;
+use hyper::{Request, Response, StatusCode};
+
+use crate::config::probe_config::TargetConfig;
+use crate::exposition::format::{ExpositionFormat, render};
+use crate::http_probe::probe::{ProbeContext, probe_target};
+use crate::http_probe::result::ProbeResult;
+use crate::http_probe::trace::TraceContext;
+use crate::mimir::metadata::probe_metadata;
+use crate::mimir::{HTTP_MODULE, create_probe_metrics, probe_phases, remove_target_labels};
+
+use super::{query_param, response, text_response};
+
+/// The modules accepted by the `/probe` endpoint. Next to its own module, Oxybox accepts the module
+/// name of the Blackbox Exporter example configuration, so existing scrape configs keep working.
+const MODULES: &[&str] = &[HTTP_MODULE, "http_2xx"];
+
+/// The timeout of a probe when Prometheus does not send its scrape timeout.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The time subtracted from the scrape timeout of Prometheus, to leave room to send the response.
+const TIMEOUT_OFFSET: Duration = Duration::from_millis(500);
+
+/// Serves `/probe?target=<url>&module=<module>`, probing the target immediately and returning its
+/// metrics, like the Blackbox Exporter. Any 2xx status code is accepted.
+/// The `instance`, `job`, `module` and `target` labels are left out, as Prometheus attaches them.
+/// With `debug=true`, a log of every phase of the probe is returned along with the metrics.
+pub async fn probe(request: &Request<Incoming>, context: &ProbeContext) -> Response<Full<Bytes>> {
+    let Some(target) = query_param(request, "target").filter(|t| !t.is_empty()) else {
+        return text_response(StatusCode::BAD_REQUEST, "Target parameter is missing\n");
+    };
+    let module = query_param(request, "module").unwrap_or_else(|| HTTP_MODULE.to_string());
+    if !MODULES.contains(&module.as_str()) {
+        return text_response(
+            StatusCode::BAD_REQUEST,
+            &format!("Unknown module \"{module}\"\n"),
+        );
+    }
+    let debug = query_param(request, "debug").is_some_and(|d| d == "true");
+
+    // just like Blackbox Exporter, targets without a scheme are probed over http
+    let url = match target.contains("://") {
+        true => target,
+        false => format!("http://{target}"),
+    };
+
+    let timeout = scrape_timeout(request);
+    let started_at_ms = Utc::now().timestamp_millis();
+    let target = TargetConfig {
+        url: url.clone(),
+        ..Default::default()
+    };
+    let (probe, error) = match tokio::time::timeout(timeout, probe_target(&target, context)).await {
+        Ok(result) => result,
+        Err(_) => (
+            ProbeResult::failed(&url, started_at_ms, TraceContext::new()),
+            Some(format!(
+                "Probe timed out after {:.1}s",
+                timeout.as_secs_f64()
+            )),
+        ),
+    };
+    let success = error.is_none()
+        && probe
+            .http_status
+            .is_some_and(|status| (200..300).contains(&status));
+
+    let mut metrics = create_probe_metrics(&probe, success, started_at_ms);
+    remove_target_labels(&mut metrics);
+
+    if debug {
+        let mut body = debug_log(&probe, &module, timeout, success, error.as_deref());
+        body.push_str("\nMetrics that would have been returned:\n");
+        body.push_str(&render(
+            &metrics,
+            ExpositionFormat::Prometheus,
+            &probe_metadata(),
+        ));
+        return text_response(StatusCode::OK, &body);
+    }
+
+    let accept = request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    let format = ExpositionFormat::from_accept(accept);
+    response(
+        StatusCode::OK,
+        format.content_type(),
+        render(&metrics, format, &probe_metadata()),
+    )
+}
+
+/// Returns the timeout of the probe, derived from the scrape timeout Prometheus sends along.
+fn scrape_timeout(request: &Request<Incoming>) -> Duration {
+    request
+        .headers()
+        .get("X-Prometheus-Scrape-Timeout-Seconds")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<f64>().ok())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .map(|timeout| timeout.saturating_sub(TIMEOUT_OFFSET))
+        .filter(|timeout| !timeout.is_zero())
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// Creates a human readable log of every phase of a probe, for debugging a target with `curl` or
+/// `oxybox check`.
+pub fn debug_log(
+    probe: &ProbeResult,
+    module: &str,
+    timeout: Duration,
+    success: bool,
+    error: Option<&str>,
+) -> String {
+    let mut log = String::from("Logs for the probe:\n");
+    let _ = writeln!(
+        log,
+        "Probing {} with module {module} and timeout {:.1}s",
+        probe.url,
+        timeout.as_secs_f64()
+    );
+    let _ = writeln!(log, "Trace context: {}", probe.trace_context.traceparent());
+
+    for (duration, phase) in probe_phases(probe) {
+        match duration {
+            Some(duration) => {
+                let _ = writeln!(log, "Phase {phase:<10} took {:.2}ms", duration * 1000.0);
+            }
+            None => {
+                let _ = writeln!(log, "Phase {phase:<10} was not performed");
+            }
+        }
+    }
+
+    if let Some(status) = probe.http_status {
+        let _ = writeln!(log, "Received HTTP status code {status}");
+    }
+    if let Some(version) = probe.http_version {
+        let _ = writeln!(log, "Received HTTP version {version:.1}");
+    }
+    if let Some(expiry) = probe
+        .cert_validity_seconds
+        .and_then(|seconds| DateTime::from_timestamp(seconds as i64, 0))
+    {
+        let days = (expiry - Utc::now()).num_seconds() as f64 / 86400.0;
+        let _ = writeln!(
+            log,
+            "Earliest certificate expires at {expiry} ({days:.2} days)"
+        );
+    }
+    if let Some(error) = error {
+        let _ = writeln!(log, "Error: {error}");
+    }
+    let _ = writeln!(
+        log,
+        "Probe {} after {:.2}ms",
+        if success { "succeeded" } else { "failed" },
+        probe.total_probe_time * 1000.0
+    );
+    log
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_log() {
+        let mut probe = ProbeResult::failed("https://example.com", 0, TraceContext::new());
+        probe.dns_time = Some(0.012);
+        probe.http_status = Some(503);
+
+        let log = debug_log(&probe, "http_2xx", DEFAULT_TIMEOUT, false, None);
+        assert!(log.contains("Probing https://example.com with module http_2xx"));
+        assert!(log.contains("Phase resolve    took 12.00ms"));
+        assert!(log.contains("Phase tls        was not performed"));
+        assert!(log.contains("Received HTTP status code 503"));
+        assert!(log.contains("Probe failed"));
+    }
+}
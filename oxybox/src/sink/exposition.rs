@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::exposition::LatestSeries;
+
+use super::{MetricsBatch, MetricsSink};
+
+/// Keeps the latest series of the tenant for the `/metrics` endpoint.
+pub struct ExpositionSink {
+    latest: Arc<LatestSeries>,
+}
+
+impl ExpositionSink {
+    pub fn new(latest: Arc<LatestSeries>) -> Self {
+        ExpositionSink { latest }
+    }
+}
+
+#[async_trait]
+impl MetricsSink for ExpositionSink {
+    fn name(&self) -> &'static str {
+        "exposition"
+    }
+
+    fn is_in_memory(&self) -> bool {
+        true
+    }
+
+    async fn write(&self, batch: &MetricsBatch<'_>) -> Result<(), Box<dyn std::error::Error>> {
+        self.latest
+            .update(batch.tenant, batch.org_id, batch.metrics, batch.metadata);
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::json::to_json_lines;
+use super::{MetricsBatch, MetricsSink};
+
+/// Configuration of a sink writing newline delimited JSON to a file, which is rotated by size.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FileSinkConfig {
+    /// The file to write to. Rotated files get a `.1`, `.2`, ... suffix, `.1` being the newest.
+    pub path: PathBuf,
+
+    /// The size in bytes at which the file is rotated. Defaults to 100 MiB.
+    #[serde(default = "default_max_size_bytes")]
+    pub max_size_bytes: u64,
+
+    /// The number of rotated files to keep. Defaults to 5.
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+}
+
+fn default_max_size_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_max_files() -> usize {
+    5
+}
+
+/// The open file of a `FileSink`, with the number of bytes it holds.
+struct OpenFile {
+    writer: BufWriter<File>,
+    size: u64,
+}
+
+/// Writes every sample as a line of JSON to a file, rotating it once it exceeds its maximum size.
+/// The file is written on the blocking thread pool, so a slow disk does not stall the probe loops.
+pub struct FileSink {
+    writer: Arc<FileWriter>,
+}
+
+impl FileSink {
+    pub fn new(config: FileSinkConfig) -> Self {
+        FileSink {
+            writer: Arc::new(FileWriter {
+                config,
+                file: Mutex::new(None),
+            }),
+        }
+    }
+}
+
+/// The blocking part of a `FileSink`, shared with the blocking tasks writing the batches.
+struct FileWriter {
+    config: FileSinkConfig,
+    file: Mutex<Option<OpenFile>>,
+}
+
+impl FileWriter {
+    /// Writes the lines to the file, opening it if needed and rotating it when it is full.
+    /// The lines are flushed right away, so every batch is on disk once it is written.
+    fn append(&self, lines: &str) -> std::io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        let open_file = match file.as_mut() {
+            Some(open_file) => open_file,
+            None => {
+                let handle = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.config.path)?;
+                let size = handle.metadata()?.len();
+                file.insert(OpenFile {
+                    writer: BufWriter::new(handle),
+                    size,
+                })
+            }
+        };
+
+        open_file.writer.write_all(lines.as_bytes())?;
+        open_file.writer.flush()?;
+        open_file.size += lines.len() as u64;
+
+        if open_file.size >= self.config.max_size_bytes {
+            *file = None;
+            rotate(&self.config.path, self.config.max_files)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shifts the rotated files of `path` one position up, dropping the oldest, and moves `path` to `.1`.
+fn rotate(path: &Path, max_files: usize) -> std::io::Result<()> {
+    if max_files == 0 {
+        return fs::remove_file(path);
+    }
+    for index in (1..max_files).rev() {
+        let from = rotated_path(path, index);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, index + 1))?;
+        }
+    }
+    fs::rename(path, rotated_path(path, 1))
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{index}"));
+    PathBuf::from(rotated)
+}
+
+#[async_trait]
+impl MetricsSink for FileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn write(&self, batch: &MetricsBatch<'_>) -> Result<(), Box<dyn std::error::Error>> {
+        let lines = to_json_lines(batch);
+        let writer = self.writer.clone();
+        tokio::task::spawn_blocking(move || writer.append(&lines)).await??;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_rotation() {
+        let dir = std::env::temp_dir().join(format!("oxybox-file-sink-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("metrics.jsonl");
+        let sink = FileSink::new(FileSinkConfig {
+            path: path.clone(),
+            max_size_bytes: 10,
+            max_files: 2,
+        });
+
+        sink.writer.append("first line\n").unwrap();
+        sink.writer.append("second line\n").unwrap();
+        sink.writer.append("third line\n").unwrap();
+        sink.writer.append("last\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "last\n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "third line\n"
+        );
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 2)).unwrap(),
+            "second line\n"
+        );
+        assert!(!rotated_path(&path, 3).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::config::interpolate::serialize_redacted;
+use crate::mimir::client::prometheus::prompb::{TimeSeries, histogram};
+
+use super::{MetricsBatch, MetricsSink};
+
+const NAME_LABEL: &str = "__name__";
+
+/// Configuration of a sink writing InfluxDB line protocol to the InfluxDB v2 write API.
+/// InfluxDB 1.8 and later accept these writes as well, using `database/retention_policy` as bucket.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct InfluxDbConfig {
+    /// The base URL of InfluxDB (e.g., "http://localhost:8086").
+    pub url: String,
+
+    /// The bucket to write to.
+    pub bucket: String,
+
+    /// The organisation owning the bucket, required by InfluxDB 2.
+    #[serde(default)]
+    pub org: Option<String>,
+
+    /// The API token to authenticate with.
+    #[serde(default, serialize_with = "serialize_redacted")]
+    pub token: Option<String>,
+}
+
+/// Writes the series to InfluxDB in line protocol, with the metric name as measurement, the labels
+/// as tags and the sample value as `value` field.
+pub struct InfluxDbSink {
+    config: InfluxDbConfig,
+    client: Client,
+}
+
+impl InfluxDbSink {
+    pub fn new(config: InfluxDbConfig) -> Self {
+        InfluxDbSink {
+            config,
+            client: Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl MetricsSink for InfluxDbSink {
+    fn name(&self) -> &'static str {
+        "influxdb"
+    }
+
+    async fn write(&self, batch: &MetricsBatch<'_>) -> Result<(), Box<dyn std::error::Error>> {
+        let body = to_line_protocol(batch.metrics);
+        if body.is_empty() {
+            return Ok(());
+        }
+
+        let mut query = vec![("bucket", self.config.bucket.as_str()), ("precision", "ms")];
+        if let Some(org) = &self.config.org {
+            query.push(("org", org));
+        }
+        let mut request = self
+            .client
+            .post(format!(
+                "{}/api/v2/write",
+                self.config.url.trim_end_matches('/')
+            ))
+            .query(&query)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(body);
+        if let Some(token) = &self.config.token {
+            request = request.header("Authorization", format!("Token {token}"));
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Failed to write to InfluxDB: {status} {body}").into());
+        }
+        Ok(())
+    }
+}
+
+/// Encodes the series as InfluxDB line protocol, with millisecond timestamps.
+/// Staleness markers are skipped, as line protocol does not support NaN values, and native
+/// histograms are written with `count` and `sum` fields.
+fn to_line_protocol(metrics: &[TimeSeries]) -> String {
+    let mut lines = String::new();
+    for series in metrics {
+        let Some(name) = series.labels.iter().find(|label| label.name == NAME_LABEL) else {
+            continue;
+        };
+        let mut tags: Vec<_> = series
+            .labels
+            .iter()
+            .filter(|label| label.name != NAME_LABEL && !label.value.is_empty())
+            .collect();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut key = escape(&name.value, &[',', ' ']);
+        for tag in tags {
+            key.push_str(&format!(
+                ",{}={}",
+                escape(&tag.name, &[',', '=', ' ']),
+                escape(&tag.value, &[',', '=', ' '])
+            ));
+        }
+
+        for sample in series.samples.iter().filter(|s| s.value.is_finite()) {
+            lines.push_str(&format!(
+                "{key} value={} {}\n",
+                sample.value, sample.timestamp
+            ));
+        }
+        for histogram in &series.histograms {
+            let count = match histogram.count {
+                Some(histogram::Count::CountInt(count)) => count as f64,
+                Some(histogram::Count::CountFloat(count)) => count,
+                None => 0.0,
+            };
+            lines.push_str(&format!(
+                "{key} count={count},sum={} {}\n",
+                histogram.sum, histogram.timestamp
+            ));
+        }
+    }
+    lines
+}
+
+/// Escapes the special characters of a line protocol element with a backslash.
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mimir::client::create_time_series;
+    use crate::mimir::staleness::STALE_NAN;
+
+    #[test]
+    fn test_to_line_protocol() {
+        let labels = [
+            ("target", "https://example.com/a b"),
+            ("phase", "tls"),
+            ("job", "oxybox"),
+        ];
+        let metrics = vec![
+            create_time_series("probe_http_duration_seconds", &labels, 0.25, Some(1_000)),
+            create_time_series("probe_success", &labels, STALE_NAN, Some(1_000)),
+        ];
+
+        assert_eq!(
+            to_line_protocol(&metrics),
+            "probe_http_duration_seconds,job=oxybox,phase=tls,target=https://example.com/a\\ b value=0.25 1000\n"
+        );
+    }
+}
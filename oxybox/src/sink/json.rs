@@ -0,0 +1,118 @@
+use serde_json::{Map, Value, json};
+
+use crate::mimir::client::prometheus::prompb::{Label, histogram};
+use crate::mimir::staleness::is_stale_marker;
+
+use super::MetricsBatch;
+
+const NAME_LABEL: &str = "__name__";
+
+/// Encodes a batch as newline delimited JSON, with one object per sample or histogram.
+/// Staleness markers are written with a `null` value and `"stale": true`, as JSON has no NaN.
+pub fn to_json_lines(batch: &MetricsBatch<'_>) -> String {
+    let mut lines = String::new();
+    for series in batch.metrics {
+        let name = series
+            .labels
+            .iter()
+            .find(|label| label.name == NAME_LABEL)
+            .map(|label| label.value.as_str())
+            .unwrap_or_default();
+        let base = json!({
+            "tenant": batch.tenant,
+            "organisation_id": batch.org_id,
+            "name": name,
+            "labels": to_json_labels(&series.labels),
+        });
+
+        for sample in &series.samples {
+            let mut line = base.clone();
+            line["timestamp_ms"] = json!(sample.timestamp);
+            match is_stale_marker(sample.value) {
+                true => {
+                    line["value"] = Value::Null;
+                    line["stale"] = json!(true);
+                }
+                false => line["value"] = json!(sample.value),
+            }
+            if !series.exemplars.is_empty() {
+                line["exemplars"] = series
+                    .exemplars
+                    .iter()
+                    .map(|exemplar| {
+                        json!({
+                            "labels": to_json_labels(&exemplar.labels),
+                            "value": exemplar.value,
+                            "timestamp_ms": exemplar.timestamp,
+                        })
+                    })
+                    .collect();
+            }
+            lines.push_str(&line.to_string());
+            lines.push('\n');
+        }
+
+        for histogram in &series.histograms {
+            let count = match histogram.count {
+                Some(histogram::Count::CountInt(count)) => count as f64,
+                Some(histogram::Count::CountFloat(count)) => count,
+                None => 0.0,
+            };
+            let mut line = base.clone();
+            line["timestamp_ms"] = json!(histogram.timestamp);
+            line["histogram"] = json!({
+                "count": count,
+                "sum": histogram.sum,
+                "schema": histogram.schema,
+            });
+            lines.push_str(&line.to_string());
+            lines.push('\n');
+        }
+    }
+    lines
+}
+
+fn to_json_labels(labels: &[Label]) -> Value {
+    labels
+        .iter()
+        .filter(|label| label.name != NAME_LABEL)
+        .map(|label| (label.name.clone(), Value::String(label.value.clone())))
+        .collect::<Map<String, Value>>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mimir::client::create_time_series;
+    use crate::mimir::staleness::STALE_NAN;
+
+    #[test]
+    fn test_to_json_lines() {
+        let labels = [("instance", "https://example.com")];
+        let metrics = vec![
+            create_time_series("probe_success", &labels, 1.0, Some(1_000)),
+            create_time_series("probe_http_status_code", &labels, STALE_NAN, Some(1_000)),
+        ];
+        let batch = MetricsBatch {
+            tenant: "Tenant",
+            org_id: "org-1",
+            metrics: &metrics,
+            metadata: &[],
+            created_timestamps: &[],
+        };
+
+        let lines: Vec<Value> = to_json_lines(&batch)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["name"], "probe_success");
+        assert_eq!(lines[0]["organisation_id"], "org-1");
+        assert_eq!(lines[0]["labels"]["instance"], "https://example.com");
+        assert_eq!(lines[0]["value"], 1.0);
+        assert_eq!(lines[0]["timestamp_ms"], 1_000);
+        assert!(lines[1]["value"].is_null());
+        assert_eq!(lines[1]["stale"], true);
+    }
+}
@@ -0,0 +1,105 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::mimir::client::{
+    MimirEndpoint, RemoteWrite, RemoteWriteVersion, is_retryable, send_to_mimir,
+};
+use crate::mimir::metadata::MetadataSchedule;
+use crate::self_monitoring::SELF_METRICS;
+
+use super::{MetricsBatch, MetricsSink};
+
+/// The number of times a push is retried when it may succeed later, e.g. on a server error.
+const MAX_RETRIES: u32 = 2;
+
+/// The wait before the first retry of a push, doubling with every retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Configuration of a sink pushing to Mimir, overriding the settings of the Mimir endpoint.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct MimirSinkConfig {
+    /// The base URL of the Mimir instance. Defaults to `MIMIR_ENDPOINT`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// The remote write protocol version. Defaults to `MIMIR_REMOTE_WRITE_VERSION`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_write_version: Option<RemoteWriteVersion>,
+}
+
+/// Pushes the series to Mimir using Prometheus remote write, with the organisation ID as tenant.
+/// The metadata of a batch is sent along with every push when using Remote Write 2.0, otherwise
+/// once per `metadata_send_interval`. The created timestamps of the series are only sent with Remote
+/// Write 2.0, as 1.0 has no place for them.
+/// A push failing with a server error, rate limiting or a connection error is retried, counted in
+/// `oxybox_remote_write_retries_total`, while a push which still fails is counted in
+/// `oxybox_remote_write_failures_total`.
+pub struct MimirSink {
+    endpoint: MimirEndpoint,
+    metadata_schedule: Mutex<MetadataSchedule>,
+}
+
+impl MimirSink {
+    /// Creates a sink pushing to the Mimir endpoint, with the settings of `config` taking precedence.
+    pub fn new(config: &MimirSinkConfig, endpoint: &MimirEndpoint) -> Self {
+        let endpoint = MimirEndpoint {
+            url: config.url.clone().unwrap_or_else(|| endpoint.url.clone()),
+            remote_write_version: config
+                .remote_write_version
+                .unwrap_or(endpoint.remote_write_version),
+            ..endpoint.clone()
+        };
+        MimirSink {
+            metadata_schedule: Mutex::new(MetadataSchedule::new(endpoint.metadata_send_interval)),
+            endpoint,
+        }
+    }
+}
+
+#[async_trait]
+impl MetricsSink for MimirSink {
+    fn name(&self) -> &'static str {
+        "mimir"
+    }
+
+    async fn write(&self, batch: &MetricsBatch<'_>) -> Result<(), Box<dyn std::error::Error>> {
+        let send_metadata = match self.endpoint.remote_write_version {
+            RemoteWriteVersion::V2 => true,
+            RemoteWriteVersion::V1 => {
+                !batch.metadata.is_empty() && self.metadata_schedule.lock().unwrap().due()
+            }
+        };
+        let metadata = match send_metadata {
+            true => batch.metadata,
+            false => &[],
+        };
+        let mut retries = 0;
+        loop {
+            let send = send_to_mimir(
+                &self.endpoint.url,
+                Some(batch.org_id),
+                RemoteWrite {
+                    metrics: batch.metrics.to_vec(),
+                    metadata: metadata.to_vec(),
+                    created_timestamps: batch.created_timestamps.to_vec(),
+                    version: self.endpoint.remote_write_version,
+                },
+            );
+            // the error is not Send, so only whether to retry is kept across the backoff
+            match send.await {
+                Err(e) if retries < MAX_RETRIES && is_retryable(e.as_ref()) => {}
+                Err(e) => {
+                    SELF_METRICS.record_remote_write_failure(batch.tenant);
+                    return Err(e);
+                }
+                Ok(()) => return Ok(()),
+            }
+            SELF_METRICS.record_remote_write_retry(batch.tenant);
+            tokio::time::sleep(RETRY_BACKOFF * 2u32.pow(retries)).await;
+            retries += 1;
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use async_trait::async_trait;
+use futures::future::join_all;
+
+use crate::config::probe_config::SinkConfig;
+use crate::mimir::client::prometheus::prompb::{MetricMetadata, TimeSeries};
+use crate::mimir::client::{ExternalLabels, MimirEndpoint};
+use crate::self_monitoring::SELF_METRICS;
+use crate::self_monitoring::health::HEALTH;
+
+pub mod exposition;
+pub mod file;
+pub mod influxdb;
+pub mod json;
+pub mod mimir;
+pub mod otlp;
+pub mod stdout;
+
+/// The series of a single push of a tenant, written to every sink of the organisation.
+pub struct MetricsBatch<'a> {
+    /// The tenant name, as configured in the probe configuration.
+    pub tenant: &'a str,
+
+    /// The organisation ID, the Mimir tenant the metrics belong to.
+    pub org_id: &'a str,
+
+    /// The series to write, as built by `create_probe_metrics`.
+    pub metrics: &'a [TimeSeries],
+
+    /// The metadata of the metric families, may be empty. Sinks decide themselves how often they
+    /// send it along.
+    pub metadata: &'a [MetricMetadata],
+
+    /// The created timestamps of the series in `metrics`, see `RemoteWrite::created_timestamps`.
+    /// May be shorter than `metrics`, or empty, for series without one.
+    pub created_timestamps: &'a [i64],
+}
+
+/// A destination for probe metrics, such as Mimir or an OpenTelemetry collector.
+#[async_trait]
+pub trait MetricsSink: Send + Sync {
+    /// The name of the sink, used in log messages.
+    fn name(&self) -> &'static str;
+
+    /// Writes a batch of series to the sink.
+    /// # Errors
+    ///     Returns an error if the series cannot be delivered.
+    async fn write(&self, batch: &MetricsBatch<'_>) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Whether the sink keeps the series in memory, rather than delivering them somewhere. Such sinks
+    /// cannot fail, so they do not count towards the readiness of Oxybox.
+    fn is_in_memory(&self) -> bool {
+        false
+    }
+
+    /// Flushes series the sink buffered, e.g. before shutting down.
+    async fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// The sinks of an organisation, every batch is written to all of them.
+pub struct MetricsSinks {
+    sinks: Vec<Box<dyn MetricsSink>>,
+
+    /// The labels added to every series before it is written.
+    external_labels: ExternalLabels,
+}
+
+impl MetricsSinks {
+    /// Creates the sinks configured for an organisation.
+    /// # Arguments
+    ///     * `configs` - The sinks of the organisation.
+    ///     * `mimir_endpoint` - The Mimir endpoint used by the `mimir` sinks, unless they set their own.
+    ///     * `external_labels` - The labels added to every series written to the sinks.
+    pub fn from_config(
+        configs: &[SinkConfig],
+        mimir_endpoint: &MimirEndpoint,
+        external_labels: &ExternalLabels,
+    ) -> Self {
+        let sinks = configs
+            .iter()
+            .map(|config| -> Box<dyn MetricsSink> {
+                match config {
+                    SinkConfig::Mimir(config) => {
+                        Box::new(mimir::MimirSink::new(config, mimir_endpoint))
+                    }
+                    SinkConfig::Otlp(endpoint) => Box::new(otlp::OtlpSink::new(endpoint.clone())),
+                    SinkConfig::Stdout => Box::new(stdout::StdoutSink),
+                    SinkConfig::File(config) => Box::new(file::FileSink::new(config.clone())),
+                    SinkConfig::Influxdb(config) => {
+                        Box::new(influxdb::InfluxDbSink::new(config.clone()))
+                    }
+                }
+            })
+            .collect();
+        MetricsSinks {
+            sinks,
+            external_labels: external_labels.clone(),
+        }
+    }
+
+    /// Adds a sink to the sinks of the organisation.
+    pub fn with_sink(mut self, sink: Box<dyn MetricsSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Writes a batch to every sink, with the external labels added first, so every sink receives the
+    /// same series. The sinks are written to concurrently, so a slow or retrying sink does not delay
+    /// the others, and a failing sink does not keep the batch from them.
+    /// Failures are counted in `oxybox_sink_write_failures_total`, by tenant and sink, while whether
+    /// the batch reached all sinks, leaving out the in-memory ones, counts towards the readiness of
+    /// Oxybox.
+    /// # Errors
+    ///     Returns an error naming the sinks which failed.
+    pub async fn write(&self, batch: &MetricsBatch<'_>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut metrics = batch.metrics.to_vec();
+        self.external_labels.apply(&mut metrics);
+        let batch = &MetricsBatch {
+            metrics: &metrics,
+            ..*batch
+        };
+
+        // the errors are not Send, so they are turned into messages as soon as a sink completes
+        let results = join_all(self.sinks.iter().map(|sink| async move {
+            sink.write(batch)
+                .await
+                .map_err(|e| format!("{}: {e}", sink.name()))
+        }))
+        .await;
+
+        let mut errors = Vec::new();
+        for (sink, result) in self.sinks.iter().zip(results) {
+            if let Err(e) = result {
+                SELF_METRICS.record_write_failure(batch.tenant, sink.name());
+                errors.push(e);
+            }
+        }
+        if self.sinks.iter().any(|sink| !sink.is_in_memory()) {
+            HEALTH.record_write(batch.tenant, errors.is_empty());
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors.join(", ").into()),
+        }
+    }
+
+    /// Flushes every sink.
+    /// # Errors
+    ///     Returns an error naming the sinks which failed to flush.
+    pub async fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut errors = Vec::new();
+        for sink in &self.sinks {
+            if let Err(e) = sink.flush().await {
+                errors.push(format!("{}: {e}", sink.name()));
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors.join(", ").into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::sync::Barrier;
+
+    use super::*;
+
+    /// A sink which only completes once every other sink sharing its barrier is being written to.
+    struct BarrierSink(Arc<Barrier>);
+
+    #[async_trait]
+    impl MetricsSink for BarrierSink {
+        fn name(&self) -> &'static str {
+            "barrier"
+        }
+
+        async fn write(&self, _batch: &MetricsBatch<'_>) -> Result<(), Box<dyn std::error::Error>> {
+            self.0.wait().await;
+            Ok(())
+        }
+
+        fn is_in_memory(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_to_sinks_concurrently() {
+        let barrier = Arc::new(Barrier::new(2));
+        let sinks = MetricsSinks::from_config(
+            &[],
+            &MimirEndpoint {
+                url: "http://localhost:9009".to_string(),
+                remote_write_version: Default::default(),
+                metadata_send_interval: Default::default(),
+            },
+            &ExternalLabels::default(),
+        )
+        .with_sink(Box::new(BarrierSink(barrier.clone())))
+        .with_sink(Box::new(BarrierSink(barrier)));
+
+        let batch = MetricsBatch {
+            tenant: "Tenant",
+            org_id: "org-1",
+            metrics: &[],
+            metadata: &[],
+            created_timestamps: &[],
+        };
+        let write = tokio::time::timeout(Duration::from_secs(5), sinks.write(&batch));
+        assert!(
+            write
+                .await
+                .expect("The sinks were written one by one")
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+
+use crate::otlp::client::{METRICS, OtlpEndpoint, export};
+use crate::otlp::metrics::to_export_metrics_request;
+
+use super::{MetricsBatch, MetricsSink};
+
+/// Exports the series to an OpenTelemetry collector as OTLP metrics.
+pub struct OtlpSink {
+    endpoint: OtlpEndpoint,
+}
+
+impl OtlpSink {
+    pub fn new(endpoint: OtlpEndpoint) -> Self {
+        OtlpSink { endpoint }
+    }
+}
+
+#[async_trait]
+impl MetricsSink for OtlpSink {
+    fn name(&self) -> &'static str {
+        "otlp"
+    }
+
+    async fn write(&self, batch: &MetricsBatch<'_>) -> Result<(), Box<dyn std::error::Error>> {
+        if batch.metrics.is_empty() {
+            return Ok(());
+        }
+        let request =
+            to_export_metrics_request(batch.tenant, batch.org_id, batch.metrics, batch.metadata);
+        export(&self.endpoint, &METRICS, &request).await
+    }
+}
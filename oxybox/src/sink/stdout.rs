@@ -0,0 +1,28 @@
+use std::io::Write;
+
+use async_trait::async_trait;
+
+use super::json::to_json_lines;
+use super::{MetricsBatch, MetricsSink};
+
+/// Writes every sample as a line of JSON to stdout, for local debugging.
+pub struct StdoutSink;
+
+#[async_trait]
+impl MetricsSink for StdoutSink {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    async fn write(&self, batch: &MetricsBatch<'_>) -> Result<(), Box<dyn std::error::Error>> {
+        std::io::stdout()
+            .lock()
+            .write_all(to_json_lines(batch).as_bytes())?;
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), Box<dyn std::error::Error>> {
+        std::io::stdout().lock().flush()?;
+        Ok(())
+    }
+}